@@ -1,6 +1,8 @@
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
+use std::io::Write;
 use std::iter;
+use tempfile::TempPath;
 
 /// Generates a random string of characters of the given length.
 pub fn rand_string(length: usize) -> String {
@@ -12,6 +14,52 @@ pub fn rand_string(length: usize) -> String {
         .collect()
 }
 
+/// Generates a random version 4 UUID.
+pub fn rand_uuid() -> String {
+    let mut bytes: [u8; 16] = thread_rng().gen();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Writes the given content to a new temporary file with the given mode.
+///
+/// The file is removed once the returned [TempPath] is dropped, so it must be
+/// kept alive for as long as the file is needed (i.e. when it's bind mounted
+/// into a container).
+pub fn write_tempfile(
+    prefix: &str,
+    suffix: &str,
+    content: &[u8],
+    mode: u32,
+) -> std::io::Result<TempPath> {
+    let mut file = tempfile::Builder::new()
+        .prefix(prefix)
+        .suffix(suffix)
+        .rand_bytes(10)
+        .tempfile()?;
+    file.write_all(content)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(file.path(), std::fs::Permissions::from_mode(mode))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+
+    Ok(file.into_temp_path())
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -19,4 +67,17 @@ mod tests {
         let result = super::rand_string(10);
         assert_eq!(result.len(), 10);
     }
+
+    #[test]
+    fn test_rand_uuid() {
+        let result = super::rand_uuid();
+        assert_eq!(result.len(), 36);
+        assert_eq!(result.chars().nth(14), Some('4'));
+    }
+
+    #[test]
+    fn test_write_tempfile() {
+        let path = super::write_tempfile("test", ".txt", b"hello", 0o644).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+    }
 }
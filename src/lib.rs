@@ -67,6 +67,9 @@ pub mod common;
 pub mod server;
 pub mod servers;
 pub mod test;
+#[cfg(any(feature = "database", feature = "hashi"))]
+pub mod tls;
+pub mod waitfor;
 
 pub use server::{new_handle, Config, ContainerConfig, Server};
 pub use test::{Test, TestInstance};
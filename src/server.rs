@@ -16,6 +16,17 @@ use dockertest::{waitfor::WaitFor, Composition, Image, RunningContainer, Source}
 pub trait Config: Clone + Send + Sync {
     fn into_composition(self) -> Composition;
     fn handle(&self) -> &str;
+
+    /// Returns all of the [Compositions][Composition] required by this
+    /// [Config].
+    ///
+    /// Most configurations map to a single container and can rely on the
+    /// default implementation. Configurations which bring up multiple
+    /// containers (i.e. a cluster) should override it, in which case `handle`
+    /// must return the handle of the container passed to [Server::new].
+    fn into_compositions(self) -> Vec<Composition> {
+        vec![self.into_composition()]
+    }
 }
 
 /// A running instance of a specific container generated by a [Config].
@@ -7,5 +7,7 @@ pub mod cloud;
 pub mod database;
 #[cfg(feature = "hashi")]
 pub mod hashi;
+#[cfg(feature = "search")]
+pub mod search;
 #[cfg(feature = "webserver")]
 pub mod webserver;
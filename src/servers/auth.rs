@@ -1,3 +1,7 @@
+pub mod keycloak;
+pub mod ldap;
 pub mod oidc;
 
+pub use keycloak::{KeycloakServer, KeycloakServerConfig};
+pub use ldap::{LdapServer, LdapServerConfig};
 pub use oidc::{OIDCServer, OIDCServerConfig};
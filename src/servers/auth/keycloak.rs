@@ -0,0 +1,306 @@
+use crate::common::{rand_string, write_tempfile};
+use crate::waitfor::{retry, HookWait};
+use crate::{Config, ContainerConfig, Server};
+use derive_builder::Builder;
+use dockertest::Source;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tempfile::TempPath;
+
+const IMAGE: &str = "quay.io/keycloak/keycloak";
+const IMPORT_DIR: &str = "/opt/keycloak/data/import";
+const PORT: u32 = 8080;
+const SOURCE: Source = Source::DockerHub;
+
+/// Configuration for creating a Keycloak server.
+///
+/// By default the Keycloak server listens on port 8080 for HTTP requests. This
+/// is exposed on the container by default, but the exposed port can be
+/// controlled by setting the `port` field.
+///
+/// The server runs in development mode with an administrator account created
+/// using the `admin_user` and `admin_password` fields. If the password is
+/// omitted it will automatically be generated. Realm exports can be imported
+/// on startup by adding them with `add_realm` or `add_realm_file`.
+///
+/// See the [Keycloak](https://www.keycloak.org/server/containers)
+/// documentation for more information on the arguments and environment
+/// variables that can be used to configure the server.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct KeycloakServerConfig {
+    #[builder(default = "rand_string(16)")]
+    pub admin_password: String,
+    #[builder(default = "String::from(\"admin\")")]
+    pub admin_user: String,
+    #[builder(default = "Vec::new()")]
+    pub args: Vec<String>,
+    #[builder(default = "HashMap::new()")]
+    pub bind_mounts: HashMap<String, String>,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "8080")]
+    pub port: u32,
+    #[builder(default = "60")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"latest\")")]
+    pub version: String,
+}
+
+impl KeycloakServerConfig {
+    pub fn builder() -> KeycloakServerConfigBuilder {
+        KeycloakServerConfigBuilder::default()
+    }
+
+    /// Imports the realm export found at `local_path` on startup.
+    pub fn add_realm_file(&mut self, local_path: &str) {
+        let remote_path = format!("{}/realm{}.json", IMPORT_DIR, self.bind_mounts.len());
+        self.bind_mounts.insert(remote_path, local_path.to_string());
+    }
+
+    /// Imports the given realm export on startup.
+    ///
+    /// The realm is written to a temporary file which is removed when the
+    /// returned [TempPath] is dropped. It must be kept alive until the test
+    /// has finished running.
+    pub fn add_realm(&mut self, realm: &[u8]) -> std::io::Result<TempPath> {
+        let path = write_tempfile("realm", ".json", realm, 0o644)?;
+        self.add_realm_file(&path.to_string_lossy());
+        Ok(path)
+    }
+}
+
+impl Config for KeycloakServerConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        let ports = vec![(PORT, self.port)];
+
+        let mut env = self.env.clone();
+        for key in ["KEYCLOAK_ADMIN", "KC_BOOTSTRAP_ADMIN_USERNAME"] {
+            env.insert(key.to_string(), self.admin_user.clone());
+        }
+        for key in ["KEYCLOAK_ADMIN_PASSWORD", "KC_BOOTSTRAP_ADMIN_PASSWORD"] {
+            env.insert(key.to_string(), self.admin_password.clone());
+        }
+
+        let mut args = vec![String::from("start-dev"), String::from("--import-realm")];
+        args.extend(self.args.clone());
+
+        let url = format!(
+            "http://localhost:{}/realms/master/.well-known/openid-configuration",
+            self.port
+        );
+        let timeout = self.timeout;
+        let wait = Box::new(HookWait::running(timeout, move |_| {
+            let url = url.clone();
+            async move {
+                let client = reqwest::Client::new();
+                retry(timeout, || async {
+                    client
+                        .get(&url)
+                        .send()
+                        .await
+                        .and_then(|r| r.error_for_status())
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                })
+                .await
+            }
+        }));
+
+        ContainerConfig {
+            args,
+            env,
+            handle: self.handle,
+            name: IMAGE.into(),
+            source: SOURCE,
+            version: self.version,
+            ports: Some(ports),
+            wait: Some(wait),
+            bind_mounts: self.bind_mounts,
+        }
+        .into()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// A running instance of a Keycloak server.
+///
+/// The `admin_user` and `admin_password` fields contain the credentials of
+/// the administrator account in the `master` realm. The server URL which is
+/// accessible from the local host can be found with `external_url`. Other
+/// running containers which need access to this server should use
+/// `internal_url` instead.
+pub struct KeycloakServer {
+    pub admin_password: String,
+    pub admin_user: String,
+    pub external_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+}
+
+impl KeycloakServer {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external admin console address
+    pub fn admin_url(&self) -> String {
+        format!("{}/admin", self.external_url())
+    }
+
+    /// The external address in the form of localhost:{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external issuer URL for the given realm
+    pub fn external_issuer_url(&self, realm: &str) -> String {
+        format!("{}/realms/{}", self.external_url(), realm)
+    }
+
+    /// The external HTTP address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal issuer URL for the given realm
+    pub fn internal_issuer_url(&self, realm: &str) -> String {
+        format!("{}/realms/{}", self.internal_url(), realm)
+    }
+
+    /// The internal HTTP address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+
+    /// Returns an access token for the given user.
+    ///
+    /// The token is obtained using the resource owner password credentials
+    /// grant, which must be enabled on the given client. The `client_secret`
+    /// is only required for confidential clients.
+    pub async fn token(
+        &self,
+        realm: &str,
+        client_id: &str,
+        client_secret: Option<&str>,
+        username: &str,
+        password: &str,
+    ) -> Result<String, reqwest::Error> {
+        let mut form = vec![
+            ("grant_type", "password"),
+            ("client_id", client_id),
+            ("username", username),
+            ("password", password),
+        ];
+        if let Some(secret) = client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let resp: TokenResponse = reqwest::Client::new()
+            .post(format!(
+                "{}/protocol/openid-connect/token",
+                self.external_issuer_url(realm)
+            ))
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(resp.access_token)
+    }
+
+    /// Returns an access token for the administrator account.
+    pub async fn admin_token(&self) -> Result<String, reqwest::Error> {
+        self.token(
+            "master",
+            "admin-cli",
+            None,
+            &self.admin_user,
+            &self.admin_password,
+        )
+        .await
+    }
+}
+
+impl Server for KeycloakServer {
+    type Config = KeycloakServerConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        KeycloakServer {
+            admin_password: config.admin_password.clone(),
+            admin_user: config.admin_user.clone(),
+            external_port: config.port,
+            internal_port: PORT,
+            ip: container.ip().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeycloakServer, KeycloakServerConfig};
+    use crate::Test;
+    use test_log::test;
+
+    const PORT: u32 = 9180;
+    const REALM: &str = r#"{
+        "realm": "test",
+        "enabled": true,
+        "clients": [
+            {
+                "clientId": "test",
+                "publicClient": true,
+                "directAccessGrantsEnabled": true
+            }
+        ],
+        "users": [
+            {
+                "username": "user",
+                "enabled": true,
+                "email": "user@example.com",
+                "firstName": "Test",
+                "lastName": "User",
+                "credentials": [{ "type": "password", "value": "password" }]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_keycloak() {
+        let mut config = KeycloakServerConfig::builder().port(PORT).build().unwrap();
+        let _realm = config.add_realm(REALM.as_bytes()).unwrap();
+
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: KeycloakServer = instance.server();
+
+            let token = server.admin_token().await;
+            assert!(token.is_ok());
+
+            let token = server.token("test", "test", None, "user", "password").await;
+            assert!(token.is_ok());
+        });
+    }
+}
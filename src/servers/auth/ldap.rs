@@ -0,0 +1,401 @@
+use crate::common::{rand_string, write_tempfile};
+use crate::waitfor::{retry, HookWait};
+use crate::{Config, ContainerConfig, Server};
+use derive_builder::Builder;
+use dockertest::{waitfor, Source};
+use std::collections::HashMap;
+use tempfile::TempPath;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const IMAGE: &str = "osixia/openldap";
+const LDIF_DIR: &str = "/container/service/slapd/assets/config/bootstrap/ldif/custom";
+const LOG_MSG: &str = "First start is done...";
+const PORT: u32 = 389;
+const SOURCE: Source = Source::DockerHub;
+
+/// Configuration for creating an OpenLDAP server.
+///
+/// By default the LDAP server listens on port 389 for requests. This is
+/// exposed on the container as port 1389 by default, but the exposed port can
+/// be controlled by setting the `port` field.
+///
+/// The directory is created using the `base_dn` field with an administrator
+/// account of `cn=admin,{base_dn}` using the `admin_password` field. If the
+/// password is omitted it will automatically be generated. LDIF documents
+/// added with `add_ldif` or `add_ldif_file` are loaded into the directory
+/// before the server is considered ready.
+///
+/// See the [Github](https://github.com/osixia/docker-openldap) repo for more
+/// information on the arguments and environment variables that can be used to
+/// configure the server.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct LdapServerConfig {
+    #[builder(default = "rand_string(16)")]
+    pub admin_password: String,
+    #[builder(default = "Vec::new()")]
+    pub args: Vec<String>,
+    #[builder(default = "String::from(\"dc=example,dc=org\")")]
+    pub base_dn: String,
+    #[builder(default = "HashMap::new()")]
+    pub bind_mounts: HashMap<String, String>,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "1389")]
+    pub port: u32,
+    #[builder(default = "30")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"1.5.0\")")]
+    pub version: String,
+}
+
+impl LdapServerConfig {
+    pub fn builder() -> LdapServerConfigBuilder {
+        LdapServerConfigBuilder::default()
+    }
+
+    /// Seeds the directory with the LDIF document found at `local_path`.
+    ///
+    /// Documents are loaded in the order they were added.
+    pub fn add_ldif_file(&mut self, local_path: &str) {
+        let remote_path = format!("{}/{:02}.ldif", LDIF_DIR, self.bind_mounts.len());
+        self.bind_mounts.insert(remote_path, local_path.to_string());
+    }
+
+    /// Seeds the directory with the given LDIF document.
+    ///
+    /// The document is written to a temporary file which is removed when the
+    /// returned [TempPath] is dropped. It must be kept alive until the test
+    /// has finished running.
+    pub fn add_ldif(&mut self, ldif: &[u8]) -> std::io::Result<TempPath> {
+        let path = write_tempfile("seed", ".ldif", ldif, 0o644)?;
+        self.add_ldif_file(&path.to_string_lossy());
+        Ok(path)
+    }
+
+    /// The DN of the administrator account
+    fn bind_dn(&self) -> String {
+        format!("cn=admin,{}", self.base_dn)
+    }
+
+    /// The domain equivalent of the base DN (i.e. example.org)
+    fn domain(&self) -> String {
+        self.base_dn
+            .split(',')
+            .filter_map(|c| c.trim().strip_prefix("dc="))
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+impl Config for LdapServerConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        let ports = vec![(PORT, self.port)];
+
+        let mut env = self.env.clone();
+        env.insert(String::from("LDAP_DOMAIN"), self.domain());
+        env.insert(String::from("LDAP_BASE_DN"), self.base_dn.clone());
+        env.insert(
+            String::from("LDAP_ADMIN_PASSWORD"),
+            self.admin_password.clone(),
+        );
+
+        // The bootstrap process modifies the seeded files in place
+        let mut args = vec![String::from("--copy-service")];
+        args.extend(self.args.clone());
+
+        let inner = Box::new(waitfor::MessageWait {
+            message: LOG_MSG.into(),
+            source: waitfor::MessageSource::Stdout,
+            timeout: self.timeout,
+        });
+        let address = format!("localhost:{}", self.port);
+        let (dn, password) = (self.bind_dn(), self.admin_password.clone());
+        let timeout = self.timeout;
+        let wait = Box::new(HookWait::new(inner, move |_| {
+            let (address, dn, password) = (address.clone(), dn.clone(), password.clone());
+            async move { retry(timeout, || simple_bind(&address, &dn, &password)).await }
+        }));
+
+        ContainerConfig {
+            args,
+            env,
+            handle: self.handle,
+            name: IMAGE.into(),
+            source: SOURCE,
+            version: self.version,
+            ports: Some(ports),
+            wait: Some(wait),
+            bind_mounts: self.bind_mounts,
+        }
+        .into()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// Performs an LDAPv3 simple bind against the given address.
+async fn simple_bind(address: &str, dn: &str, password: &str) -> Result<(), String> {
+    let mut bind = vec![0x02, 0x01, 0x03];
+    bind.extend(ber(0x04, dn.as_bytes()));
+    bind.extend(ber(0x80, password.as_bytes()));
+
+    let mut message = vec![0x02, 0x01, 0x01];
+    message.extend(ber(0x60, &bind));
+    let request = ber(0x30, &message);
+
+    let mut stream = TcpStream::connect(address)
+        .await
+        .map_err(|e| e.to_string())?;
+    stream
+        .write_all(&request)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let response = read_message(&mut stream).await.map_err(|e| e.to_string())?;
+    match bind_result(&response) {
+        Some(0) => Ok(()),
+        Some(code) => Err(format!("bind failed with result code {}", code)),
+        None => Err(String::from("malformed bind response")),
+    }
+}
+
+/// Reads a single BER encoded LDAPMessage from the given stream.
+async fn read_message(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut message = vec![0u8; 2];
+    stream.read_exact(&mut message).await?;
+
+    let len = match message[1] {
+        len if len < 0x80 => len as usize,
+        len => {
+            let mut bytes = vec![0u8; (len & 0x7f) as usize];
+            stream.read_exact(&mut bytes).await?;
+            message.extend(&bytes);
+            bytes.iter().fold(0, |acc, b| (acc << 8) | *b as usize)
+        }
+    };
+
+    let mut value = vec![0u8; len];
+    stream.read_exact(&mut value).await?;
+    message.extend(value);
+    Ok(message)
+}
+
+/// Returns the result code of the given BindResponse message, or [None] if
+/// the message isn't a response to the bind request.
+fn bind_result(message: &[u8]) -> Option<u8> {
+    let (tag, envelope, _) = decode(message)?;
+    if tag != 0x30 {
+        return None;
+    }
+
+    // The message ID must match the one used for the request
+    let (tag, id, rest) = decode(envelope)?;
+    if tag != 0x02 || id != [0x01] {
+        return None;
+    }
+
+    let (tag, response, _) = decode(rest)?;
+    if tag != 0x61 {
+        return None;
+    }
+
+    match decode(response)? {
+        (0x0a, [code], _) => Some(*code),
+        _ => None,
+    }
+}
+
+/// Decodes the first BER element of the given input, returning its tag, its
+/// value and the remaining input.
+fn decode(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (tag, rest) = input.split_first()?;
+    let (len, rest) = rest.split_first()?;
+    let (len, rest) = match *len {
+        len if len < 0x80 => (len as usize, rest),
+        len => {
+            let count = (len & 0x7f) as usize;
+            if count > std::mem::size_of::<usize>() || rest.len() < count {
+                return None;
+            }
+            let (bytes, rest) = rest.split_at(count);
+            (
+                bytes.iter().fold(0, |acc, b| (acc << 8) | *b as usize),
+                rest,
+            )
+        }
+    };
+
+    if rest.len() < len {
+        return None;
+    }
+    let (value, rest) = rest.split_at(len);
+    Some((*tag, value, rest))
+}
+
+/// Encodes the given tag and value using BER.
+fn ber(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match value.len() {
+        len if len < 0x80 => out.push(len as u8),
+        len => {
+            let bytes: Vec<u8> = len
+                .to_be_bytes()
+                .iter()
+                .copied()
+                .skip_while(|b| *b == 0)
+                .collect();
+            out.push(0x80 | bytes.len() as u8);
+            out.extend(bytes);
+        }
+    }
+    out.extend(value);
+    out
+}
+
+/// A running instance of an OpenLDAP server.
+///
+/// The `bind_dn` and `password` fields contain the credentials of the
+/// administrator account. The server URL which is accessible from the local
+/// host can be found with `external_url`. Other running containers which need
+/// access to this server should use `internal_url` instead.
+pub struct LdapServer {
+    pub base_dn: String,
+    pub bind_dn: String,
+    pub external_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+    pub password: String,
+}
+
+impl LdapServer {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("ldap://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost:{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external LDAP URL
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal LDAP URL
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+impl Server for LdapServer {
+    type Config = LdapServerConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        LdapServer {
+            base_dn: config.base_dn.clone(),
+            bind_dn: config.bind_dn(),
+            external_port: config.port,
+            internal_port: PORT,
+            ip: container.ip().to_string(),
+            password: config.admin_password.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simple_bind, LdapServer, LdapServerConfig};
+    use crate::Test;
+    use test_log::test;
+
+    const PORT: u32 = 1389;
+    const LDIF: &str = r#"dn: ou=people,dc=example,dc=org
+objectClass: organizationalUnit
+ou: people
+
+dn: uid=user,ou=people,dc=example,dc=org
+objectClass: inetOrgPerson
+uid: user
+cn: Test User
+sn: User
+userPassword: password
+"#;
+
+    #[test]
+    fn test_ber() {
+        assert_eq!(super::ber(0x04, b"abc"), vec![0x04, 0x03, b'a', b'b', b'c']);
+
+        let long = super::ber(0x04, &[0u8; 300]);
+        assert_eq!(&long[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+
+    #[test]
+    fn test_bind_result() {
+        // A successful response with a message ID and lengths of 0x61
+        let mut response = vec![0x30, 0x81, 0x61, 0x02, 0x01, 0x01, 0x61, 0x5c];
+        response.extend([0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x55]);
+        response.extend([0x61; 0x55]);
+        assert_eq!(super::bind_result(&response), Some(0));
+
+        // Invalid credentials
+        let response = [
+            0x30, 0x0c, 0x02, 0x01, 0x01, 0x61, 0x07, 0x0a, 0x01, 0x31, 0x04, 0x00, 0x04, 0x00,
+        ];
+        assert_eq!(super::bind_result(&response), Some(0x31));
+
+        // A response to another message
+        let response = [
+            0x30, 0x0c, 0x02, 0x01, 0x61, 0x61, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00,
+        ];
+        assert_eq!(super::bind_result(&response), None);
+
+        // A truncated response
+        assert_eq!(super::bind_result(&response[..8]), None);
+    }
+
+    #[test]
+    fn test_ldap() {
+        let mut config = LdapServerConfig::builder().port(PORT).build().unwrap();
+        let _ldif = config.add_ldif(LDIF.as_bytes()).unwrap();
+
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: LdapServer = instance.server();
+
+            let res = simple_bind(
+                &server.external_address(),
+                &server.bind_dn,
+                &server.password,
+            )
+            .await;
+            assert!(res.is_ok());
+
+            let res = simple_bind(
+                &server.external_address(),
+                "uid=user,ou=people,dc=example,dc=org",
+                "password",
+            )
+            .await;
+            assert!(res.is_ok());
+        });
+    }
+}
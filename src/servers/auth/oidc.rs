@@ -1,19 +1,131 @@
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
 use dockertest::{waitfor, Source};
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 
+const CLIENT_ID: &str = "dockertest";
 const IMAGE: &str = "ghcr.io/navikt/mock-oauth2-server";
 const PORT: u32 = 8080;
 const LOG_MSG: &str = "started server on address";
+const REDIRECT_URI: &str = "http://localhost/callback";
 const SOURCE: Source = Source::DockerHub;
 
+/// The grant types which tokens can be requested with.
+const GRANT_TYPES: &[&str] = &[
+    "authorization_code",
+    "client_credentials",
+    "password",
+    "refresh_token",
+    "urn:ietf:params:oauth:grant-type:jwt-bearer",
+    "urn:ietf:params:oauth:grant-type:token-exchange",
+];
+
+#[derive(Debug)]
+pub enum TokenError {
+    MissingCode,
+    MissingToken,
+    Request(reqwest::Error),
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::MissingCode => write!(f, "authorization response is missing a code"),
+            TokenError::MissingToken => write!(f, "token response is missing an access token"),
+            TokenError::Request(e) => write!(f, "failed requesting token: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TokenError::Request(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl std::convert::From<reqwest::Error> for TokenError {
+    fn from(inner: reqwest::Error) -> TokenError {
+        TokenError::Request(inner)
+    }
+}
+
+/// An issuer along with the claims of the tokens it issues.
+///
+/// The `subject` and `audience` fields determine the `sub` and `aud` claims
+/// respectively, with any additional claims given in `claims`. When either is
+/// empty the server's default for that claim is used instead. Tokens expire
+/// `expiry` seconds after they're issued.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct OIDCIssuer {
+    #[builder(default = "Vec::new()")]
+    pub audience: Vec<String>,
+    #[builder(default = "HashMap::new()")]
+    pub claims: HashMap<String, Value>,
+    #[builder(default = "3600")]
+    pub expiry: u32,
+    #[builder(default = "String::from(\"default\")", setter(into))]
+    pub name: String,
+    #[builder(default = "String::new()", setter(into))]
+    pub subject: String,
+}
+
+impl OIDCIssuer {
+    pub fn builder() -> OIDCIssuerBuilder {
+        OIDCIssuerBuilder::default()
+    }
+
+    /// Returns the token callback which issues tokens for this issuer.
+    fn token_callback(&self) -> Value {
+        let mut claims = self.claims.clone();
+        if !self.subject.is_empty() {
+            claims.insert(String::from("sub"), json!(self.subject));
+        }
+        if !self.audience.is_empty() {
+            claims.insert(String::from("aud"), json!(self.audience));
+        }
+
+        let mappings: Vec<Value> = GRANT_TYPES
+            .iter()
+            .map(|grant_type| {
+                json!({
+                    "requestParam": "grant_type",
+                    "match": grant_type,
+                    "claims": claims,
+                })
+            })
+            .collect();
+        json!({
+            "issuerId": self.name,
+            "tokenExpiry": self.expiry,
+            "requestMappings": mappings,
+        })
+    }
+}
+
 /// Configuration for creating a mock OAuth (OIDC) server.
 ///
 /// By default the OAuth server listens on port 8080 for HTTP requests. This
 /// is exposed on the container by default, but the exposed port can be
 /// controlled by setting the `port` field.
 ///
+/// Any issuer is available on the server by default. The issuers in the
+/// `issuers` field are passed to the server through its JSON configuration and
+/// determine the claims of the tokens issued by each of them, regardless of
+/// the grant type used to request them.
+///
+/// Setting the `interactive_login` field makes the authorization endpoint show
+/// a login form, which allows choosing the subject and claims of each token.
+/// Tokens can be issued with [OIDCServer::issue_token] regardless of this
+/// setting.
+///
 /// See the [Github](https://github.com/navikt/mock-oauth2-server) repo for more
 /// information on the arguments and environment variables that can be used to
 /// configure the server.
@@ -26,6 +138,10 @@ pub struct OIDCServerConfig {
     pub env: HashMap<String, String>,
     #[builder(default = "crate::server::new_handle(IMAGE)")]
     pub handle: String,
+    #[builder(default = "false")]
+    pub interactive_login: bool,
+    #[builder(default = "Vec::new()")]
+    pub issuers: Vec<OIDCIssuer>,
     #[builder(default = "8200")]
     pub port: u32,
     #[builder(default = "15")]
@@ -38,12 +154,26 @@ impl OIDCServerConfig {
     pub fn builder() -> OIDCServerConfigBuilder {
         OIDCServerConfigBuilder::default()
     }
+
+    /// Returns the JSON configuration of the server.
+    fn json_config(&self) -> Value {
+        let callbacks: Vec<Value> = self.issuers.iter().map(|i| i.token_callback()).collect();
+        json!({
+            "interactiveLogin": self.interactive_login,
+            "tokenCallbacks": callbacks,
+        })
+    }
 }
 
 impl Config for OIDCServerConfig {
     fn into_composition(self) -> dockertest::Composition {
         let ports = vec![(PORT, self.port)];
 
+        let mut env = self.env.clone();
+        if self.interactive_login || !self.issuers.is_empty() {
+            env.insert(String::from("JSON_CONFIG"), self.json_config().to_string());
+        }
+
         let timeout = self.timeout;
         let wait = Box::new(waitfor::MessageWait {
             message: LOG_MSG.into(),
@@ -53,7 +183,7 @@ impl Config for OIDCServerConfig {
 
         ContainerConfig {
             args: self.args,
-            env: self.env,
+            env,
             handle: self.handle,
             name: IMAGE.into(),
             source: SOURCE,
@@ -72,9 +202,13 @@ impl Config for OIDCServerConfig {
 
 /// A running instance of a mock OAuth server.
 ///
-/// The server URL which is accessible from the local host can be found in
-/// `local_address`. Other running containers which need access to this server
-/// should use the `address` field instead.
+/// The server URL which is accessible from the local host can be found with
+/// `external_url`. Other running containers which need access to this server
+/// should use `internal_url` instead. The same applies to the issuer URLs,
+/// where `issuer_url`, `jwks_url` and `token_url` are accessible from the local
+/// host and their `internal_*` counterparts from other containers. Note that
+/// the server derives the `iss` claim of a token from the URL it was requested
+/// with.
 pub struct OIDCServer {
     pub external_port: u32,
     pub internal_port: u32,
@@ -100,6 +234,87 @@ impl OIDCServer {
         self.format_url("localhost", self.external_port)
     }
 
+    /// The external URL of the given issuer
+    pub fn issuer_url(&self, issuer: &str) -> String {
+        format!("{}/{}", self.external_url(), issuer)
+    }
+
+    /// The external URL of the JSON Web Key Set of the given issuer
+    pub fn jwks_url(&self, issuer: &str) -> String {
+        format!("{}/jwks", self.issuer_url(issuer))
+    }
+
+    /// The external URL of the token endpoint of the given issuer
+    pub fn token_url(&self, issuer: &str) -> String {
+        format!("{}/token", self.issuer_url(issuer))
+    }
+
+    /// Issues a signed access token from the given issuer containing the
+    /// given claims.
+    ///
+    /// The token is obtained by submitting the login form of the authorization
+    /// endpoint and exchanging the resulting code. The server accepts the form
+    /// whether or not `interactive_login` is enabled. The subject of the token
+    /// is taken from the `sub` claim and defaults to `user`. Any claims
+    /// configured for the issuer are included alongside the given ones.
+    pub async fn issue_token(
+        &self,
+        issuer: &str,
+        claims: HashMap<String, Value>,
+    ) -> Result<String, TokenError> {
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or("user")
+            .to_string();
+        let client = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .build()?;
+
+        let resp = client
+            .post(format!("{}/authorize", self.issuer_url(issuer)))
+            .query(&[
+                ("client_id", CLIENT_ID),
+                ("redirect_uri", REDIRECT_URI),
+                ("response_type", "code"),
+                ("scope", "openid"),
+                ("state", "state"),
+            ])
+            .form(&[("username", subject), ("claims", json!(claims).to_string())])
+            .send()
+            .await?;
+        let code = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| Url::parse(l).ok())
+            .and_then(|u| {
+                u.query_pairs()
+                    .find(|(k, _)| k == "code")
+                    .map(|(_, v)| v.to_string())
+            })
+            .ok_or(TokenError::MissingCode)?;
+
+        let resp: Value = client
+            .post(self.token_url(issuer))
+            .form(&[
+                ("client_id", CLIENT_ID),
+                ("client_secret", "secret"),
+                ("code", code.as_str()),
+                ("grant_type", "authorization_code"),
+                ("redirect_uri", REDIRECT_URI),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        resp["access_token"]
+            .as_str()
+            .map(String::from)
+            .ok_or(TokenError::MissingToken)
+    }
+
     /// The container internal address in the form of {ip}:{port}
     pub fn internal_address(&self) -> String {
         self.format_address(self.ip.as_str(), self.internal_port)
@@ -109,6 +324,21 @@ impl OIDCServer {
     pub fn internal_url(&self) -> String {
         self.format_url(self.ip.as_str(), self.internal_port)
     }
+
+    /// The internal URL of the given issuer
+    pub fn internal_issuer_url(&self, issuer: &str) -> String {
+        format!("{}/{}", self.internal_url(), issuer)
+    }
+
+    /// The internal URL of the JSON Web Key Set of the given issuer
+    pub fn internal_jwks_url(&self, issuer: &str) -> String {
+        format!("{}/jwks", self.internal_issuer_url(issuer))
+    }
+
+    /// The internal URL of the token endpoint of the given issuer
+    pub fn internal_token_url(&self, issuer: &str) -> String {
+        format!("{}/token", self.internal_issuer_url(issuer))
+    }
 }
 
 impl Server for OIDCServer {
@@ -125,8 +355,11 @@ impl Server for OIDCServer {
 
 #[cfg(test)]
 mod tests {
-    use super::{OIDCServer, OIDCServerConfig};
+    use super::{OIDCIssuer, OIDCServer, OIDCServerConfig, TokenError};
     use crate::Test;
+    use base64::Engine;
+    use serde_json::{json, Value};
+    use std::collections::HashMap;
     use test_log::test;
 
     const PORT: u32 = 9080;
@@ -152,4 +385,110 @@ mod tests {
             assert_eq!(resp.unwrap().status(), 200);
         });
     }
+
+    #[test]
+    fn test_json_config() {
+        let issuer = OIDCIssuer::builder()
+            .name("test")
+            .subject("user")
+            .build()
+            .unwrap();
+        let config = OIDCServerConfig::builder()
+            .issuers(vec![issuer])
+            .build()
+            .unwrap();
+
+        let callback = &config.json_config()["tokenCallbacks"][0];
+        assert_eq!(callback["issuerId"], "test");
+        assert_eq!(callback["tokenExpiry"], 3600);
+        assert_eq!(callback["requestMappings"][0]["claims"]["sub"], "user");
+        assert!(callback["requestMappings"][0]["claims"]["aud"].is_null());
+
+        let issuer = OIDCIssuer::builder().build().unwrap();
+        let claims = &issuer.token_callback()["requestMappings"][0]["claims"];
+        assert_eq!(claims, &json!({}));
+    }
+
+    #[test]
+    fn test_token_error() {
+        let err: Box<dyn std::error::Error> = Box::new(TokenError::MissingToken);
+        assert_eq!(err.to_string(), "token response is missing an access token");
+        assert!(err.source().is_none());
+    }
+
+    #[test]
+    fn test_oidc_issuers() {
+        let issuer = OIDCIssuer::builder()
+            .audience(vec!["api".into()])
+            .claims(HashMap::from([(String::from("role"), json!("admin"))]))
+            .name("test")
+            .subject("user")
+            .build()
+            .unwrap();
+        let config = OIDCServerConfig::builder()
+            .issuers(vec![issuer])
+            .port(PORT + 1)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: OIDCServer = instance.server();
+
+            let client = reqwest::Client::new();
+            let resp: Value = client
+                .post(server.token_url("test"))
+                .form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", "client"),
+                    ("client_secret", "secret"),
+                ])
+                .send()
+                .await
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+
+            let token = resp["access_token"].as_str().unwrap();
+            let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(token.split('.').nth(1).unwrap())
+                .unwrap();
+            let claims: Value = serde_json::from_slice(&payload).unwrap();
+            assert_eq!(claims["iss"], server.issuer_url("test"));
+            assert_eq!(claims["sub"], "user");
+            assert_eq!(claims["aud"], json!(["api"]));
+            assert_eq!(claims["role"], "admin");
+        });
+    }
+
+    #[test]
+    fn test_oidc_issue_token() {
+        let config = OIDCServerConfig::builder().port(PORT + 2).build().unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: OIDCServer = instance.server();
+            let token = server
+                .issue_token(
+                    "test",
+                    HashMap::from([
+                        (String::from("sub"), json!("admin")),
+                        (String::from("role"), json!("admin")),
+                    ]),
+                )
+                .await
+                .unwrap();
+
+            let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                .decode(token.split('.').nth(1).unwrap())
+                .unwrap();
+            let claims: Value = serde_json::from_slice(&payload).unwrap();
+            assert_eq!(claims["iss"], server.issuer_url("test"));
+            assert_eq!(claims["sub"], "admin");
+            assert_eq!(claims["role"], "admin");
+        });
+    }
 }
@@ -1,3 +1,6 @@
+mod aws;
 pub mod localstack;
+pub mod minio;
 
 pub use localstack::{LocalStackServer, LocalStackServerConfig};
+pub use minio::{MinioServer, MinioServerConfig};
@@ -0,0 +1,255 @@
+/// A minimal implementation of AWS Signature Version 4 request signing.
+///
+/// This is only intended for provisioning resources on S3-compatible and AWS
+/// emulating servers after they've started and makes no attempt at being a
+/// complete implementation of the specification.
+use hmac::{Hmac, KeyInit, Mac};
+use reqwest::{Client, Method, RequestBuilder, Url};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Credentials and region used for signing a request.
+#[derive(Clone)]
+pub(crate) struct Credentials {
+    pub access_key: String,
+    pub secret_key: String,
+    pub region: String,
+}
+
+/// Returns a [RequestBuilder] for the given request with a SigV4 signature
+/// attached for the given service.
+pub(crate) fn signed_request(
+    client: &Client,
+    creds: &Credentials,
+    service: &str,
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+) -> Result<RequestBuilder, String> {
+    let url = Url::parse(url).map_err(|e| e.to_string())?;
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let (date, timestamp) = timestamp(SystemTime::now());
+    let payload_hash = hex::encode(Sha256::digest(&body));
+
+    let mut signed: Vec<(String, String)> = headers
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.trim().to_string()))
+        .collect();
+    signed.push(("host".into(), host));
+    signed.push(("x-amz-content-sha256".into(), payload_hash.clone()));
+    signed.push(("x-amz-date".into(), timestamp.clone()));
+
+    let authorization = authorization(
+        creds,
+        service,
+        &method,
+        &url,
+        signed,
+        &payload_hash,
+        (&date, &timestamp),
+    );
+
+    let mut request = client
+        .request(method, url)
+        .header("x-amz-content-sha256", payload_hash)
+        .header("x-amz-date", timestamp)
+        .header("authorization", authorization)
+        .body(body);
+    for (k, v) in headers {
+        request = request.header(*k, *v);
+    }
+
+    Ok(request)
+}
+
+/// Returns the value of the authorization header for the given request.
+///
+/// The signed headers must include the `host` and `x-amz-date` headers, with
+/// the latter matching the given timestamp.
+fn authorization(
+    creds: &Credentials,
+    service: &str,
+    method: &Method,
+    url: &Url,
+    mut signed: Vec<(String, String)>,
+    payload_hash: &str,
+    (date, timestamp): (&str, &str),
+) -> String {
+    signed.sort();
+
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(k, v)| format!("{}:{}\n", k, v))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(k, _)| k.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+
+    let mut query: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(k, v)| (encode(&k, true), encode(&v, true)))
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method.as_str(),
+        url.path(),
+        canonical_query,
+        canonical_headers,
+        signed_headers,
+        payload_hash
+    );
+
+    let scope = format!("{}/{}/{}/aws4_request", date, creds.region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = hmac(
+        format!("AWS4{}", creds.secret_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac(&key, creds.region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    let key = hmac(&key, b"aws4_request");
+    let signature = hex::encode(hmac(&key, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        creds.access_key, scope, signed_headers, signature
+    )
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// URI encodes the given string as described by the SigV4 specification.
+fn encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b'/' if !encode_slash => "/".to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Returns the date (YYYYMMDD) and timestamp (YYYYMMDD'T'HHMMSS'Z') for the
+/// given time.
+fn timestamp(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // Converts days since the epoch into a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let timestamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    );
+    (date, timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Credentials;
+    use reqwest::{Method, Url};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // Taken from the get-vanilla and get-vanilla-query-order-key-case cases
+    // of the AWS SigV4 test suite
+    #[test]
+    fn test_authorization() {
+        let creds = Credentials {
+            access_key: "AKIDEXAMPLE".into(),
+            secret_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            region: "us-east-1".into(),
+        };
+        let headers = vec![
+            ("host".to_string(), "example.amazonaws.com".to_string()),
+            ("x-amz-date".to_string(), "20150830T123600Z".to_string()),
+        ];
+        let payload_hash = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let cases = [
+            (
+                "https://example.amazonaws.com/",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            ),
+            (
+                "https://example.amazonaws.com/?Param2=value2&Param1=value1",
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500",
+            ),
+        ];
+
+        for (url, signature) in cases {
+            let result = super::authorization(
+                &creds,
+                "service",
+                &Method::GET,
+                &Url::parse(url).unwrap(),
+                headers.clone(),
+                payload_hash,
+                ("20150830", "20150830T123600Z"),
+            );
+            assert_eq!(
+                result,
+                format!(
+                    "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature={}",
+                    signature
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(super::encode("a b/c~", true), "a%20b%2Fc~");
+        assert_eq!(super::encode("a b/c~", false), "a%20b/c~");
+    }
+
+    #[test]
+    fn test_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_secs(1440938160);
+        let (date, timestamp) = super::timestamp(time);
+        assert_eq!(date, "20150830");
+        assert_eq!(timestamp, "20150830T123600Z");
+    }
+}
@@ -1,19 +1,108 @@
+use super::aws::{signed_request, Credentials};
+use crate::common::write_tempfile;
+use crate::waitfor::{retry, HookWait};
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
-use dockertest::{waitfor, Source};
+use dockertest::Source;
+use reqwest::{Client, Method};
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempPath;
 
+const ACCESS_KEY: &str = "test";
 const IMAGE: &str = "localstack/localstack";
+const INIT_DIR: &str = "/etc/localstack/init/ready.d";
 const PORT: u32 = 4566;
-const LOG_MSG: &str = "Ready.";
+const SECRET_KEY: &str = "test";
 const SOURCE: Source = Source::DockerHub;
 
+/// A script which is run once the server is ready.
+///
+/// Scripts are either an inline shell script or the path to a script on the
+/// local host. Local scripts may be any of the types supported by LocalStack
+/// (i.e. `.sh` or `.py`).
+#[derive(Clone)]
+pub enum LocalStackInitScript {
+    File(String),
+    Shell(String),
+}
+
+/// An S3 bucket along with the objects to upload to it, keyed by their key.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct LocalStackBucket {
+    #[builder(default = "String::new()", setter(into))]
+    pub name: String,
+    #[builder(default = "HashMap::new()")]
+    pub objects: HashMap<String, Vec<u8>>,
+}
+
+impl LocalStackBucket {
+    pub fn builder() -> LocalStackBucketBuilder {
+        LocalStackBucketBuilder::default()
+    }
+}
+
+/// A DynamoDB table.
+///
+/// The hash key and the optional range key are both string attributes. Tables
+/// are created using on-demand billing.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct LocalStackTable {
+    #[builder(default = "String::new()", setter(into))]
+    pub hash_key: String,
+    #[builder(default = "String::new()", setter(into))]
+    pub name: String,
+    #[builder(default = "None", setter(into, strip_option))]
+    pub range_key: Option<String>,
+}
+
+impl LocalStackTable {
+    pub fn builder() -> LocalStackTableBuilder {
+        LocalStackTableBuilder::default()
+    }
+}
+
+/// The ARNs of the resources created on a [LocalStackServer], keyed by the
+/// name of each resource.
+#[derive(Clone, Debug, Default)]
+pub struct LocalStackArns {
+    pub buckets: HashMap<String, String>,
+    pub queues: HashMap<String, String>,
+    pub secrets: HashMap<String, String>,
+    pub tables: HashMap<String, String>,
+    pub topics: HashMap<String, String>,
+}
+
 /// Configuration for creating a LocalStack server
 ///
 /// By default the LocalStack server listens on port 4566 for requests. This
 /// is exposed on the container by default, but the exposed port can be
 /// controlled by setting the `port` field.
 ///
+/// The services listed in `services` are the only ones enabled on the server.
+/// When empty, the services listed in the `SERVICES` environment variable are
+/// used instead and if that isn't set either, all services are available and
+/// loaded on first use. The server is only considered ready once the health
+/// endpoint reports each of the enabled services as either running or
+/// available. When all services are available, the server is instead
+/// considered ready once LocalStack reports its ready stage as completed.
+///
+/// The S3 buckets, SQS queues, SNS topics, DynamoDB tables and Secrets Manager
+/// secrets (keyed by name and containing the secret string) listed in the
+/// respective fields are created once the server is ready and before the test
+/// body runs. Queues and topics with a `.fifo` suffix are created as FIFO
+/// queues and topics. All resources are created in the region given by the
+/// `region` field using the dummy credentials exposed by [LocalStackServer].
+///
+/// The scripts in the `init_scripts` field are mounted into the `ready.d` init
+/// directory and run, in order, by LocalStack once it's ready. The test body
+/// only runs once all of them have completed and fails if any of them did
+/// not succeed.
+///
 /// See the [DockerHub](https://hub.docker.com/localstack/localstack) repo for
 /// more information on the arguments and environment variables that can be
 /// used to configure the server.
@@ -22,44 +111,425 @@ const SOURCE: Source = Source::DockerHub;
 pub struct LocalStackServerConfig {
     #[builder(default = "Vec::new()")]
     pub args: Vec<String>,
+    #[builder(default = "Vec::new()")]
+    pub buckets: Vec<LocalStackBucket>,
     #[builder(default = "HashMap::new()")]
     pub env: HashMap<String, String>,
     #[builder(default = "crate::server::new_handle(IMAGE)")]
     pub handle: String,
+    #[builder(default = "Vec::new()")]
+    pub init_scripts: Vec<LocalStackInitScript>,
     #[builder(default = "PORT")]
     pub port: u32,
+    #[builder(default = "Vec::new()")]
+    pub queues: Vec<String>,
+    #[builder(default = "String::from(\"us-east-1\")")]
+    pub region: String,
+    #[builder(default = "HashMap::new()")]
+    pub secrets: HashMap<String, String>,
+    #[builder(default = "Vec::new()")]
+    pub services: Vec<String>,
+    #[builder(default = "Vec::new()")]
+    pub tables: Vec<LocalStackTable>,
     #[builder(default = "15")]
     pub timeout: u16,
+    #[builder(default = "Vec::new()")]
+    pub topics: Vec<String>,
     #[builder(default = "String::from(\"latest\")")]
     pub version: String,
+    #[builder(setter(skip))]
+    state: Arc<Mutex<LocalStackState>>,
+}
+
+/// Data generated while a [LocalStackServerConfig] is brought up.
+#[derive(Default)]
+struct LocalStackState {
+    arns: LocalStackArns,
+    files: Vec<TempPath>,
+    services: HashMap<String, String>,
 }
 
 impl LocalStackServerConfig {
     pub fn builder() -> LocalStackServerConfigBuilder {
         LocalStackServerConfigBuilder::default()
     }
+
+    fn credentials(&self) -> Credentials {
+        Credentials {
+            access_key: ACCESS_KEY.into(),
+            secret_key: SECRET_KEY.into(),
+            region: self.region.clone(),
+        }
+    }
+
+    fn url(&self) -> String {
+        format!("http://localhost:{}", self.port)
+    }
+
+    /// Sends a signed request to the server and returns the response body.
+    async fn send(
+        &self,
+        client: &Client,
+        service: &str,
+        method: Method,
+        url: &str,
+        headers: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<String, String> {
+        signed_request(
+            client,
+            &self.credentials(),
+            service,
+            method,
+            url,
+            headers,
+            body,
+        )?
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())
+    }
+
+    /// Calls the given target of a JSON protocol API.
+    async fn call(
+        &self,
+        client: &Client,
+        service: &str,
+        content_type: &str,
+        target: &str,
+        body: Value,
+    ) -> Result<Value, String> {
+        let response = self
+            .send(
+                client,
+                service,
+                Method::POST,
+                &self.url(),
+                &[("content-type", content_type), ("x-amz-target", target)],
+                body.to_string().into_bytes(),
+            )
+            .await
+            .map_err(|e| format!("failed calling {}: {}", target, e))?;
+        serde_json::from_str(&response).map_err(|e| e.to_string())
+    }
+
+    /// Waits for the configured services to become available, creates all
+    /// configured resources and then waits for the init scripts to complete.
+    async fn ready(self) -> Result<(), String> {
+        let client = Client::new();
+        let services = self.services();
+        self.wait_for_services(&client, &services).await?;
+
+        // Without any services to poll, LocalStack is only known to be ready
+        // once it has run its ready stage
+        if services.is_empty() {
+            self.wait_for_init(&client).await?;
+        }
+        self.provision(&client).await?;
+        if !self.init_scripts.is_empty() {
+            self.wait_for_init(&client).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the bind mounts for the configured init scripts.
+    fn init_mounts(&self) -> Result<HashMap<String, String>, String> {
+        let mut state = self.state.lock().unwrap();
+        self.init_scripts
+            .iter()
+            .enumerate()
+            .map(|(i, script)| {
+                let (name, local_path) = match script {
+                    LocalStackInitScript::File(path) => {
+                        let name = Path::new(path)
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        (name, path.clone())
+                    }
+                    LocalStackInitScript::Shell(script) => {
+                        let file = write_tempfile("init", ".sh", script.as_bytes(), 0o755)
+                            .map_err(|e| format!("failed writing init script: {}", e))?;
+                        let path = file.to_string_lossy().to_string();
+                        state.files.push(file);
+                        (String::from("init.sh"), path)
+                    }
+                };
+                Ok((format!("{}/{:02}-{}", INIT_DIR, i, name), local_path))
+            })
+            .collect()
+    }
+
+    /// The services enabled on the server, taken from the `SERVICES`
+    /// environment variable when `services` is empty.
+    fn services(&self) -> Vec<String> {
+        match (self.services.is_empty(), self.env.get("SERVICES")) {
+            (true, Some(services)) => services
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            _ => self.services.clone(),
+        }
+    }
+
+    /// Polls the init endpoint until all scripts in the ready stage have
+    /// completed, failing if any of them did not succeed.
+    async fn wait_for_init(&self, client: &Client) -> Result<(), String> {
+        let url = format!("{}/_localstack/init/ready", self.url());
+        let scripts = retry(self.timeout, || async {
+            let init: Value = client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            match init["completed"].as_bool() {
+                Some(true) => Ok(init["scripts"].as_array().cloned().unwrap_or_default()),
+                _ => Err(String::from("init scripts have not completed")),
+            }
+        })
+        .await?;
+
+        match scripts.iter().find(|s| s["state"] != "SUCCESSFUL") {
+            Some(script) => Err(format!(
+                "init script {} failed with state {}",
+                script["name"], script["state"]
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Polls the health endpoint until every given service is either running
+    /// or available, recording the status of all services.
+    async fn wait_for_services(&self, client: &Client, pending: &[String]) -> Result<(), String> {
+        let url = format!("{}/_localstack/health", self.url());
+        let services = retry(self.timeout, || async {
+            let health: Value = client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            let services: HashMap<String, String> = health["services"]
+                .as_object()
+                .map(|services| {
+                    services
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.as_str().unwrap_or_default().to_string()))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let pending = pending.iter().find(|service| {
+                !matches!(
+                    services.get(*service).map(String::as_str),
+                    Some("running") | Some("available")
+                )
+            });
+            match pending {
+                Some(service) => Err(format!("service {} is not available", service)),
+                None => Ok(services),
+            }
+        })
+        .await?;
+
+        self.state.lock().unwrap().services = services;
+        Ok(())
+    }
+
+    /// Creates all configured resources and records their ARNs.
+    async fn provision(&self, client: &Client) -> Result<(), String> {
+        let mut arns = LocalStackArns::default();
+
+        for bucket in self.buckets.iter() {
+            // Buckets outside of us-east-1 must specify their location
+            let body = match self.region.as_str() {
+                "us-east-1" => String::new(),
+                region => format!(
+                    "<CreateBucketConfiguration><LocationConstraint>{}</LocationConstraint>\
+                     </CreateBucketConfiguration>",
+                    region
+                ),
+            };
+            let url = format!("{}/{}", self.url(), bucket.name);
+            self.send(client, "s3", Method::PUT, &url, &[], body.into_bytes())
+                .await
+                .map_err(|e| format!("failed creating bucket {}: {}", bucket.name, e))?;
+
+            for (key, content) in bucket.objects.iter() {
+                self.send(
+                    client,
+                    "s3",
+                    Method::PUT,
+                    &format!("{}/{}", url, key),
+                    &[],
+                    content.clone(),
+                )
+                .await
+                .map_err(|e| format!("failed uploading object {}: {}", key, e))?;
+            }
+
+            arns.buckets
+                .insert(bucket.name.clone(), format!("arn:aws:s3:::{}", bucket.name));
+        }
+
+        for queue in self.queues.iter() {
+            let attributes = match queue.ends_with(".fifo") {
+                true => json!({"FifoQueue": "true"}),
+                false => json!({}),
+            };
+            let response = self
+                .call(
+                    client,
+                    "sqs",
+                    "application/x-amz-json-1.0",
+                    "AmazonSQS.CreateQueue",
+                    json!({"QueueName": queue, "Attributes": attributes}),
+                )
+                .await?;
+            let response = self
+                .call(
+                    client,
+                    "sqs",
+                    "application/x-amz-json-1.0",
+                    "AmazonSQS.GetQueueAttributes",
+                    json!({"QueueUrl": response["QueueUrl"], "AttributeNames": ["QueueArn"]}),
+                )
+                .await?;
+            arns.queues.insert(
+                queue.clone(),
+                response["Attributes"]["QueueArn"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+        }
+
+        for topic in self.topics.iter() {
+            let mut body = format!("Action=CreateTopic&Version=2010-03-31&Name={}", topic);
+            if topic.ends_with(".fifo") {
+                body.push_str("&Attributes.entry.1.key=FifoTopic&Attributes.entry.1.value=true");
+            }
+            let response = self
+                .send(
+                    client,
+                    "sns",
+                    Method::POST,
+                    &self.url(),
+                    &[("content-type", "application/x-www-form-urlencoded")],
+                    body.into_bytes(),
+                )
+                .await
+                .map_err(|e| format!("failed creating topic {}: {}", topic, e))?;
+            arns.topics.insert(
+                topic.clone(),
+                xml_value(&response, "TopicArn").unwrap_or_default(),
+            );
+        }
+
+        for table in self.tables.iter() {
+            let mut attributes =
+                vec![json!({"AttributeName": table.hash_key, "AttributeType": "S"})];
+            let mut schema = vec![json!({"AttributeName": table.hash_key, "KeyType": "HASH"})];
+            if let Some(range_key) = &table.range_key {
+                attributes.push(json!({"AttributeName": range_key, "AttributeType": "S"}));
+                schema.push(json!({"AttributeName": range_key, "KeyType": "RANGE"}));
+            }
+
+            let response = self
+                .call(
+                    client,
+                    "dynamodb",
+                    "application/x-amz-json-1.0",
+                    "DynamoDB_20120810.CreateTable",
+                    json!({
+                        "AttributeDefinitions": attributes,
+                        "BillingMode": "PAY_PER_REQUEST",
+                        "KeySchema": schema,
+                        "TableName": table.name,
+                    }),
+                )
+                .await?;
+            arns.tables.insert(
+                table.name.clone(),
+                response["TableDescription"]["TableArn"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+        }
+
+        for (name, secret) in self.secrets.iter() {
+            let response = self
+                .call(
+                    client,
+                    "secretsmanager",
+                    "application/x-amz-json-1.1",
+                    "secretsmanager.CreateSecret",
+                    json!({"Name": name, "SecretString": secret}),
+                )
+                .await?;
+            arns.secrets.insert(
+                name.clone(),
+                response["ARN"].as_str().unwrap_or_default().to_string(),
+            );
+        }
+
+        self.state.lock().unwrap().arns = arns;
+        Ok(())
+    }
+}
+
+/// Returns the text of the first element with the given name in an XML
+/// document.
+fn xml_value(document: &str, element: &str) -> Option<String> {
+    let start = format!("<{}>", element);
+    let end = format!("</{}>", element);
+    let value = document.split(&start).nth(1)?.split(&end).next()?;
+    Some(value.to_string())
 }
 
 impl Config for LocalStackServerConfig {
     fn into_composition(self) -> dockertest::Composition {
         let ports = vec![(PORT, self.port)];
 
-        let wait = Box::new(waitfor::MessageWait {
-            message: LOG_MSG.into(),
-            source: waitfor::MessageSource::Stdout,
-            timeout: self.timeout,
-        });
+        let mut env = self.env.clone();
+        if !self.services.is_empty() {
+            env.insert(String::from("SERVICES"), self.services.join(","));
+        }
+
+        // Failing to write any of the init scripts fails the test
+        let config = self.clone();
+        let (bind_mounts, wait) = match self.init_mounts() {
+            Ok(mounts) => (
+                mounts,
+                HookWait::running(self.timeout, move |_| config.clone().ready()),
+            ),
+            Err(e) => (HashMap::new(), HookWait::fail(e)),
+        };
 
         ContainerConfig {
             args: self.args,
-            env: self.env,
+            env,
             handle: self.handle,
             name: IMAGE.into(),
             source: SOURCE,
             version: self.version,
             ports: Some(ports),
-            wait: Some(wait),
-            bind_mounts: HashMap::new(),
+            wait: Some(Box::new(wait)),
+            bind_mounts,
         }
         .into()
     }
@@ -70,10 +540,25 @@ impl Config for LocalStackServerConfig {
 }
 
 /// A running instance of a LocalStack server.
+///
+/// The `access_key`, `secret_key` and `region` fields contain the values
+/// needed to configure an AWS client against the server. The ARNs of the
+/// resources created on startup can be found in the `arns` field.
+///
+/// The `services` field contains the status of each service as reported by the
+/// health endpoint once the server became ready. LocalStack serves every
+/// service through the same port, so the URLs returned by
+/// `external_service_url` and `internal_service_url` share the same address
+/// and are only returned for services which were available.
 pub struct LocalStackServer {
+    pub access_key: String,
+    pub arns: LocalStackArns,
     pub external_port: u32,
     pub internal_port: u32,
     pub ip: String,
+    pub region: String,
+    pub secret_key: String,
+    pub services: HashMap<String, String>,
 }
 
 impl LocalStackServer {
@@ -85,6 +570,13 @@ impl LocalStackServer {
         format!("http://{}", self.format_address(host, port))
     }
 
+    fn service_url(&self, service: &str, url: String) -> Option<String> {
+        match self.services.get(service).map(String::as_str) {
+            Some("running") | Some("available") => Some(url),
+            _ => None,
+        }
+    }
+
     /// The external HTTP address
     pub fn external_url(&self) -> String {
         self.format_url("localhost", self.external_port)
@@ -100,31 +592,71 @@ impl LocalStackServer {
         self.format_address("localhost", self.external_port)
     }
 
+    /// The external HTTP address of the given service, if it's available
+    pub fn external_service_url(&self, service: &str) -> Option<String> {
+        self.service_url(service, self.external_url())
+    }
+
     /// The container internal address in the form of {ip}:{port}
     pub fn internal_address(&self) -> String {
         self.format_address(self.ip.as_str(), self.internal_port)
     }
+
+    /// The internal HTTP address of the given service, if it's available
+    pub fn internal_service_url(&self, service: &str) -> Option<String> {
+        self.service_url(service, self.internal_url())
+    }
 }
 
 impl Server for LocalStackServer {
     type Config = LocalStackServerConfig;
 
     fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        let state = config.state.lock().unwrap();
         LocalStackServer {
+            access_key: ACCESS_KEY.into(),
+            arns: state.arns.clone(),
             external_port: config.port,
             internal_port: PORT,
             ip: container.ip().to_string(),
+            region: config.region.clone(),
+            secret_key: SECRET_KEY.into(),
+            services: state.services.clone(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{LocalStackServer, LocalStackServerConfig};
+    use super::{
+        LocalStackBucket, LocalStackInitScript, LocalStackServer, LocalStackServerConfig,
+        LocalStackTable,
+    };
+    use crate::servers::cloud::aws::{signed_request, Credentials};
     use crate::Test;
+    use reqwest::Method;
     use std::collections::HashMap;
     use test_log::test;
 
+    #[test]
+    fn test_services() {
+        let config = LocalStackServerConfig::builder()
+            .env(HashMap::from([("SERVICES".into(), "iam, sts,".into())]))
+            .build()
+            .unwrap();
+        assert_eq!(config.services(), vec!["iam", "sts"]);
+
+        let config = LocalStackServerConfig::builder()
+            .env(HashMap::from([("SERVICES".into(), "iam".into())]))
+            .services(vec!["s3".into()])
+            .build()
+            .unwrap();
+        assert_eq!(config.services(), vec!["s3"]);
+
+        let config = LocalStackServerConfig::builder().build().unwrap();
+        assert!(config.services().is_empty());
+    }
+
     #[test]
     fn test_local_stack() {
         let env: HashMap<_, _> = vec![("SERVICES".to_string(), "iam,sts".to_string())]
@@ -137,6 +669,9 @@ mod tests {
 
         test.run(|instance| async move {
             let server: LocalStackServer = instance.server();
+            assert!(server.external_service_url("iam").is_some());
+            assert!(server.external_service_url("s3").is_none());
+
             let client = reqwest::Client::new();
             let resp = client
                 .get(format!("{}/health", server.external_url()))
@@ -146,4 +681,98 @@ mod tests {
             assert_eq!(resp.unwrap().status(), 200);
         });
     }
+
+    #[test]
+    fn test_local_stack_resources() {
+        let bucket = LocalStackBucket::builder()
+            .name("bucket")
+            .objects(HashMap::from([(String::from("key"), b"test".to_vec())]))
+            .build()
+            .unwrap();
+        let table = LocalStackTable::builder()
+            .hash_key("id")
+            .name("table")
+            .build()
+            .unwrap();
+        let config = LocalStackServerConfig::builder()
+            .buckets(vec![bucket])
+            .port(4666)
+            .queues(vec!["queue".into(), "queue.fifo".into()])
+            .region("eu-west-1".into())
+            .secrets(HashMap::from([(
+                String::from("secret"),
+                String::from("test"),
+            )]))
+            .services(vec![
+                "dynamodb".into(),
+                "s3".into(),
+                "secretsmanager".into(),
+                "sns".into(),
+                "sqs".into(),
+            ])
+            .tables(vec![table])
+            .timeout(60)
+            .topics(vec!["topic".into()])
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: LocalStackServer = instance.server();
+            assert_eq!(server.arns.buckets["bucket"], "arn:aws:s3:::bucket");
+            assert!(server.arns.queues["queue.fifo"].starts_with("arn:aws:sqs:eu-west-1:"));
+            assert!(server.arns.secrets["secret"].starts_with("arn:aws:secretsmanager:"));
+            assert!(server.arns.tables["table"].ends_with(":table/table"));
+            assert!(server.arns.topics["topic"].ends_with(":topic"));
+
+            let creds = Credentials {
+                access_key: server.access_key.clone(),
+                secret_key: server.secret_key.clone(),
+                region: server.region.clone(),
+            };
+            let resp = signed_request(
+                &reqwest::Client::new(),
+                &creds,
+                "s3",
+                Method::GET,
+                &format!("{}/bucket/key", server.external_url()),
+                &[],
+                Vec::new(),
+            )
+            .unwrap()
+            .send()
+            .await
+            .unwrap();
+            assert_eq!(resp.text().await.unwrap(), "test");
+        });
+    }
+
+    #[test]
+    fn test_local_stack_init() {
+        let config = LocalStackServerConfig::builder()
+            .init_scripts(vec![LocalStackInitScript::Shell(
+                "#!/bin/bash\nawslocal sqs create-queue --queue-name init".into(),
+            )])
+            .port(4667)
+            .services(vec!["sqs".into()])
+            .timeout(60)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: LocalStackServer = instance.server();
+            let resp = reqwest::Client::new()
+                .get(format!(
+                    "{}/000000000000/init?Action=GetQueueAttributes",
+                    server.external_url()
+                ))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 200);
+        });
+    }
 }
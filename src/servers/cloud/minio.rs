@@ -0,0 +1,268 @@
+use super::aws::{signed_request, Credentials};
+use crate::common::rand_string;
+use crate::waitfor::{retry, HookWait};
+use crate::{Config, ContainerConfig, Server};
+use derive_builder::Builder;
+use dockertest::Source;
+use reqwest::Method;
+use std::collections::HashMap;
+
+const IMAGE: &str = "minio/minio";
+const PORT: u32 = 9000;
+const CONSOLE_PORT: u32 = 9001;
+const SOURCE: Source = Source::DockerHub;
+
+/// Configuration for creating a MinIO server.
+///
+/// By default the MinIO server listens on port 9000 for S3 requests and on
+/// port 9001 for the web console. These are exposed on the container by
+/// default, but the exposed ports can be controlled by setting the `port` and
+/// `console_port` fields.
+///
+/// The root credentials are generated automatically unless `access_key` and
+/// `secret_key` are provided. Any buckets listed in `buckets` are created
+/// once the server is available and before the test body runs.
+///
+/// See the [DockerHub](https://hub.docker.com/r/minio/minio) repo for more
+/// information on the arguments and environment variables that can be used to
+/// configure the server.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct MinioServerConfig {
+    #[builder(default = "rand_string(16)")]
+    pub access_key: String,
+    #[builder(default = "Vec::new()")]
+    pub args: Vec<String>,
+    #[builder(default = "Vec::new()")]
+    pub buckets: Vec<String>,
+    #[builder(default = "9001")]
+    pub console_port: u32,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "9000")]
+    pub port: u32,
+    #[builder(default = "String::from(\"us-east-1\")")]
+    pub region: String,
+    #[builder(default = "rand_string(32)")]
+    pub secret_key: String,
+    #[builder(default = "15")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"latest\")")]
+    pub version: String,
+}
+
+impl MinioServerConfig {
+    pub fn builder() -> MinioServerConfigBuilder {
+        MinioServerConfigBuilder::default()
+    }
+
+    /// Waits for the server to become healthy and then creates all configured
+    /// buckets.
+    async fn provision(self) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{}", self.port);
+
+        retry(self.timeout, || async {
+            client
+                .get(format!("{}/minio/health/live", url))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+
+        let creds = Credentials {
+            access_key: self.access_key.clone(),
+            secret_key: self.secret_key.clone(),
+            region: self.region.clone(),
+        };
+        for bucket in &self.buckets {
+            signed_request(
+                &client,
+                &creds,
+                "s3",
+                Method::PUT,
+                &format!("{}/{}", url, bucket),
+                &[],
+                Vec::new(),
+            )?
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("failed creating bucket {}: {}", bucket, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Config for MinioServerConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        let ports = vec![(PORT, self.port), (CONSOLE_PORT, self.console_port)];
+
+        let mut env = self.env.clone();
+        env.insert(String::from("MINIO_ROOT_USER"), self.access_key.clone());
+        env.insert(String::from("MINIO_ROOT_PASSWORD"), self.secret_key.clone());
+        env.insert(String::from("MINIO_SITE_REGION"), self.region.clone());
+
+        let mut args = vec![
+            String::from("server"),
+            String::from("/data"),
+            String::from("--console-address"),
+            format!(":{}", CONSOLE_PORT),
+        ];
+        args.extend(self.args.clone());
+
+        let config = self.clone();
+        let wait = Box::new(HookWait::running(self.timeout, move |_| {
+            config.clone().provision()
+        }));
+
+        ContainerConfig {
+            args,
+            env,
+            handle: self.handle,
+            name: IMAGE.into(),
+            source: SOURCE,
+            version: self.version,
+            ports: Some(ports),
+            wait: Some(wait),
+            bind_mounts: HashMap::new(),
+        }
+        .into()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// A running instance of a MinIO server.
+///
+/// The `access_key`, `secret_key` and `region` fields contain the values
+/// needed to configure an S3 client against the server. The S3 endpoint which
+/// is accessible from the local host can be found with `external_url`. Other
+/// running containers which need access to this server should use
+/// `internal_url` instead.
+pub struct MinioServer {
+    pub access_key: String,
+    pub buckets: Vec<String>,
+    pub external_console_port: u32,
+    pub external_port: u32,
+    pub internal_console_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+    pub region: String,
+    pub secret_key: String,
+}
+
+impl MinioServer {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost:{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external web console address
+    pub fn external_console_url(&self) -> String {
+        self.format_url("localhost", self.external_console_port)
+    }
+
+    /// The external S3 endpoint
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal web console address
+    pub fn internal_console_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_console_port)
+    }
+
+    /// The internal S3 endpoint
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+impl Server for MinioServer {
+    type Config = MinioServerConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        MinioServer {
+            access_key: config.access_key.clone(),
+            buckets: config.buckets.clone(),
+            external_console_port: config.console_port,
+            external_port: config.port,
+            internal_console_port: CONSOLE_PORT,
+            internal_port: PORT,
+            ip: container.ip().to_string(),
+            region: config.region.clone(),
+            secret_key: config.secret_key.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MinioServer, MinioServerConfig};
+    use crate::servers::cloud::aws::{signed_request, Credentials};
+    use crate::Test;
+    use reqwest::Method;
+    use test_log::test;
+
+    const PORT: u32 = 9010;
+    const CONSOLE_PORT: u32 = 9011;
+
+    #[test]
+    fn test_minio() {
+        let config = MinioServerConfig::builder()
+            .port(PORT)
+            .console_port(CONSOLE_PORT)
+            .buckets(vec!["test".into()])
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: MinioServer = instance.server();
+            let creds = Credentials {
+                access_key: server.access_key.clone(),
+                secret_key: server.secret_key.clone(),
+                region: server.region.clone(),
+            };
+
+            let client = reqwest::Client::new();
+            let resp = signed_request(
+                &client,
+                &creds,
+                "s3",
+                Method::HEAD,
+                &format!("{}/test", server.external_url()),
+                &[],
+                Vec::new(),
+            )
+            .unwrap()
+            .send()
+            .await;
+            assert!(resp.is_ok());
+            assert_eq!(resp.unwrap().status(), 200);
+        });
+    }
+}
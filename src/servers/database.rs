@@ -1 +1,2 @@
 pub mod postgres;
+mod tls;
@@ -0,0 +1,6 @@
+#!/bin/bash
+# Requires remote connections to use TLS and present a valid client certificate
+set -e
+
+sed -i 's/^host all all all \(.*\)$/hostssl all all all \1 clientcert=verify-full/' \
+    "$PGDATA/pg_hba.conf"
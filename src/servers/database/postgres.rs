@@ -1,14 +1,73 @@
-use crate::common::rand_string;
+use super::tls::MakeRustlsConnect;
+use crate::common::{rand_string, write_tempfile};
+use crate::tls::CertificateAuthority;
+use crate::waitfor::{retry, HookWait};
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
 use dockertest::{waitfor, Source};
+use futures::FutureExt;
 use std::collections::HashMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tempfile::TempPath;
+use tokio_postgres::{Client, NoTls};
 
 const IMAGE: &str = "postgres";
+const INIT_DIR: &str = "/docker-entrypoint-initdb.d";
 const PORT: u32 = 5432;
-const LOG_MSG: &str = "database system is ready to accept connections";
+const LOG_MSG: &str = "PostgreSQL init process complete";
 const SOURCE: Source = Source::DockerHub;
-const USER: &str = "postgres";
+const TLS_DIR: &str = "/etc/postgresql/tls";
+
+/// A script which is run when the database is first initialized.
+///
+/// Scripts are either inline SQL or the path to a script on the local host.
+/// Local scripts may be any of the types supported by the image (i.e. `.sql`,
+/// `.sql.gz` or `.sh`).
+#[derive(Clone)]
+pub enum PostgresInitScript {
+    File(String),
+    Sql(String),
+}
+
+/// The image used for creating a PostgreSQL server.
+///
+/// Each flavor is a drop-in replacement for the official image which bundles
+/// additional extensions. The bundled extensions still need to be created in
+/// the database, which can be done using the `extensions` field of
+/// [PostgresServerConfig].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum PostgresFlavor {
+    PgVector,
+    PostGis,
+    #[default]
+    Postgres,
+    TimescaleDb,
+}
+
+impl PostgresFlavor {
+    /// The name of the image on DockerHub
+    pub fn image(&self) -> &str {
+        match self {
+            PostgresFlavor::PgVector => "pgvector/pgvector",
+            PostgresFlavor::PostGis => "postgis/postgis",
+            PostgresFlavor::Postgres => IMAGE,
+            PostgresFlavor::TimescaleDb => "timescale/timescaledb",
+        }
+    }
+
+    /// The image tag used when no version is given
+    pub fn default_version(&self) -> &str {
+        match self {
+            PostgresFlavor::PgVector => "pg17",
+            PostgresFlavor::PostGis => "latest",
+            PostgresFlavor::Postgres => "latest",
+            PostgresFlavor::TimescaleDb => "latest-pg17",
+        }
+    }
+}
 
 /// Configuration for creating a PostgreSQL server.
 ///
@@ -16,6 +75,30 @@ const USER: &str = "postgres";
 /// is exposed on the container by default, but the exposed port can be
 /// controlled by setting the `port` field.
 ///
+/// The server is created with a superuser using the `username` and `password`
+/// fields along with a database named by the `database` field. If the password
+/// is omitted it will automatically be generated. The scripts in the
+/// `init_scripts` field are run against the database, in order, when it's
+/// first initialized.
+///
+/// The image is determined by the `flavor` field and defaults to the official
+/// PostgreSQL image. Since the tags differ between images, the `version` field
+/// defaults to a tag which is valid for the chosen flavor. The extensions in
+/// the `extensions` field are created in the database once it's started.
+///
+/// Setting the `tls` field enables TLS using a server certificate issued by a
+/// certificate authority generated for the test. The certificate is valid for
+/// `localhost`, `127.0.0.1` and the handle of the server. Setting the
+/// `client_certs` field additionally requires remote connections to present a
+/// client certificate issued by the same authority, which is generated for the
+/// configured user. The generated files are available through the `tls` field
+/// of [PostgresServer].
+///
+/// Setting the `template` field marks the database as a template once it has
+/// been initialized (i.e. with migrations applied through the init scripts).
+/// Fresh copies of it can then be created for each test using the methods on
+/// [PostgresServer].
+///
 /// See the [DockerHub](https://hub.docker.com/_/postgres) repo for more
 /// information on the arguments and environment variables that can be used to
 /// configure the server.
@@ -24,56 +107,293 @@ const USER: &str = "postgres";
 pub struct PostgresServerConfig {
     #[builder(default = "Vec::new()")]
     pub args: Vec<String>,
+    #[builder(default = "false")]
+    pub client_certs: bool,
+    #[builder(default = "String::from(\"postgres\")")]
+    pub database: String,
     #[builder(default = "HashMap::new()")]
     pub env: HashMap<String, String>,
+    #[builder(default = "Vec::new()")]
+    pub extensions: Vec<String>,
+    #[builder(default = "PostgresFlavor::Postgres")]
+    pub flavor: PostgresFlavor,
     #[builder(default = "crate::server::new_handle(IMAGE)")]
     pub handle: String,
+    #[builder(default = "Vec::new()")]
+    pub init_scripts: Vec<PostgresInitScript>,
     #[builder(default = "rand_string(16)")]
     pub password: String,
     #[builder(default = "8200")]
     pub port: u32,
+    #[builder(default = "false")]
+    pub template: bool,
     #[builder(default = "15")]
     pub timeout: u16,
-    #[builder(default = "String::from(\"latest\")")]
+    #[builder(default = "false")]
+    pub tls: bool,
+    #[builder(default = "String::from(\"postgres\")")]
+    pub username: String,
+    #[builder(default = "self.flavor.clone().unwrap_or_default().default_version().into()")]
     pub version: String,
+    #[builder(setter(skip))]
+    state: Arc<Mutex<PostgresState>>,
+}
+
+/// Data generated while a [PostgresServerConfig] is brought up.
+#[derive(Default)]
+struct PostgresState {
+    connector: Option<MakeRustlsConnect>,
+    files: Vec<TempPath>,
+    tls: Option<PostgresTls>,
+}
+
+/// The TLS material generated for a [PostgresServer].
+///
+/// Each field contains the path to a PEM encoded file on the local host. The
+/// client certificate and key are only generated when client certificates are
+/// required by the server.
+#[derive(Clone, Debug)]
+pub struct PostgresTls {
+    pub ca_cert: String,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
 }
 
 impl PostgresServerConfig {
     pub fn builder() -> PostgresServerConfigBuilder {
         PostgresServerConfigBuilder::default()
     }
+
+    /// Waits for the server to accept connections, creates the configured
+    /// extensions and then marks the database as a template if configured to
+    /// do so.
+    async fn ready(self) -> Result<(), String> {
+        let url = |database: &str| {
+            format!(
+                "postgresql://{}:{}@localhost:{}/{}",
+                self.username, self.password, self.port, database
+            )
+        };
+        let connector = self.state.lock().unwrap().connector.clone();
+        let maintenance_url = url(maintenance_database(&self.database));
+        let client = retry(self.timeout, || async {
+            connect(&maintenance_url, connector.clone())
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+
+        if !self.extensions.is_empty() {
+            let database = connect(&url(&self.database), connector.clone())
+                .await
+                .map_err(|e| e.to_string())?;
+            for extension in self.extensions.iter() {
+                database
+                    .execute(
+                        format!("CREATE EXTENSION IF NOT EXISTS {}", quote(extension)).as_str(),
+                        &[],
+                    )
+                    .await
+                    .map_err(|e| format!("failed creating extension {}: {}", extension, e))?;
+            }
+        }
+
+        if self.template {
+            client
+                .execute(
+                    format!("ALTER DATABASE {} IS_TEMPLATE true", quote(&self.database)).as_str(),
+                    &[],
+                )
+                .await
+                .map_err(|e| format!("failed marking database as template: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the bind mounts for the configured init scripts.
+    fn init_mounts(&self) -> Result<HashMap<String, String>, String> {
+        let mut state = self.state.lock().unwrap();
+        self.init_scripts
+            .iter()
+            .enumerate()
+            .map(|(i, script)| {
+                let (name, local_path) = match script {
+                    PostgresInitScript::File(path) => {
+                        let name = Path::new(path)
+                            .file_name()
+                            .map(|n| n.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        (name, path.clone())
+                    }
+                    PostgresInitScript::Sql(sql) => {
+                        let file = write_tempfile("init", ".sql", sql.as_bytes(), 0o644)
+                            .map_err(|e| format!("failed writing init script: {}", e))?;
+                        let path = file.to_string_lossy().to_string();
+                        state.files.push(file);
+                        (String::from("init.sql"), path)
+                    }
+                };
+                Ok((format!("{}/{:02}-{}", INIT_DIR, i, name), local_path))
+            })
+            .collect()
+    }
+
+    /// Generates the TLS material for the server and returns the bind mounts
+    /// for the files it uses.
+    fn tls_mounts(&self) -> Result<HashMap<String, String>, String> {
+        let ca = CertificateAuthority::new(&self.handle).map_err(|e| e.to_string())?;
+        let server = ca
+            .issue(&self.handle, &["localhost", "127.0.0.1", &self.handle])
+            .map_err(|e| e.to_string())?;
+        let client = match self.client_certs {
+            true => Some(
+                ca.issue(&self.username, &[&self.username])
+                    .map_err(|e| e.to_string())?,
+            ),
+            false => None,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let mut write = |name: &str, suffix: &str, content: &str, mode: u32| {
+            let file = write_tempfile(name, suffix, content.as_bytes(), mode)
+                .map_err(|e| format!("failed writing TLS file: {}", e))?;
+            let path = file.to_string_lossy().to_string();
+            state.files.push(file);
+            Ok::<_, String>(path)
+        };
+
+        let ca_cert = write("ca", ".crt", &ca.cert_pem(), 0o644)?;
+        let mut mounts = HashMap::from([
+            (String::from("/tls/ca.crt"), ca_cert.clone()),
+            (
+                String::from("/tls/server.crt"),
+                write("server", ".crt", &server.cert_pem, 0o644)?,
+            ),
+            (
+                String::from("/tls/server.key"),
+                write("server", ".key", &server.key_pem, 0o644)?,
+            ),
+            (
+                String::from("/tls-entrypoint.sh"),
+                write("tls-entrypoint", ".sh", include_str!("./tls.sh"), 0o755)?,
+            ),
+        ]);
+
+        let (client_cert, client_key) = match &client {
+            Some(client) => {
+                mounts.insert(
+                    format!(
+                        "{}/{:02}-client-cert-hba.sh",
+                        INIT_DIR,
+                        self.init_scripts.len()
+                    ),
+                    write(
+                        "client-cert-hba",
+                        ".sh",
+                        include_str!("./client-cert-hba.sh"),
+                        0o755,
+                    )?,
+                );
+                (
+                    Some(write("client", ".crt", &client.cert_pem, 0o644)?),
+                    // libpq refuses keys which are accessible by other users
+                    Some(write("client", ".key", &client.key_pem, 0o600)?),
+                )
+            }
+            None => (None, None),
+        };
+
+        let connector = MakeRustlsConnect::new(
+            &ca.cert_pem(),
+            client
+                .as_ref()
+                .map(|c| (c.cert_pem.as_str(), c.key_pem.as_str())),
+        )
+        .map_err(|e| format!("failed creating TLS connector: {}", e))?;
+        state.connector = Some(connector);
+        state.tls = Some(PostgresTls {
+            ca_cert,
+            client_cert,
+            client_key,
+        });
+
+        Ok(mounts)
+    }
 }
 
 impl Config for PostgresServerConfig {
     fn into_composition(self) -> dockertest::Composition {
         let ports = vec![(PORT, self.port)];
 
+        // Failing to generate any of the mounted files fails the test
+        let mounts = self.init_mounts().and_then(|mut mounts| {
+            if self.tls {
+                mounts.extend(self.tls_mounts()?);
+            }
+            Ok(mounts)
+        });
+
         let mut env = self.env.clone();
+        env.insert(String::from("POSTGRES_DB"), self.database.clone());
         env.insert(String::from("POSTGRES_PASSWORD"), self.password.clone());
+        env.insert(String::from("POSTGRES_USER"), self.username.clone());
 
-        let mut args = self.args.clone();
+        // The TLS material is copied into place before starting the server
+        let mut args = Vec::new();
+        if self.tls {
+            args.push(String::from("bash"));
+            args.push(String::from("/tls-entrypoint.sh"));
+        }
+        args.extend(self.args.clone());
         args.push("-c".into());
         args.push("listen_addresses=*".into());
+        if self.tls {
+            args.push("-c".into());
+            args.push("ssl=on".into());
+            args.push("-c".into());
+            args.push(format!("ssl_cert_file={}/server.crt", TLS_DIR));
+            args.push("-c".into());
+            args.push(format!("ssl_key_file={}/server.key", TLS_DIR));
+            args.push("-c".into());
+            args.push(format!("ssl_ca_file={}/ca.crt", TLS_DIR));
+        }
 
+        // The server is restarted once initialization completes
         let timeout = self.timeout;
-        let wait = Box::new(waitfor::MessageWait {
+        let inner = Box::new(waitfor::MessageWait {
             message: LOG_MSG.into(),
-            source: waitfor::MessageSource::Stderr,
+            source: waitfor::MessageSource::Stdout,
             timeout,
         });
+        let config = self.clone();
+        let (bind_mounts, wait) = match mounts {
+            Ok(mounts) => (
+                mounts,
+                HookWait::new(inner, move |_| config.clone().ready()),
+            ),
+            Err(e) => (HashMap::new(), HookWait::fail(e)),
+        };
 
-        ContainerConfig {
+        let tls = self.tls;
+        let handle = self.handle.clone();
+        let mut composition: dockertest::Composition = ContainerConfig {
             args,
             env,
             handle: self.handle,
-            name: IMAGE.into(),
+            name: self.flavor.image().into(),
             source: SOURCE,
             version: self.version,
             ports: Some(ports),
-            wait: Some(wait),
-            bind_mounts: HashMap::new(),
+            wait: Some(Box::new(wait)),
+            bind_mounts,
+        }
+        .into();
+
+        // Other containers must use the handle to verify the server certificate
+        if tls {
+            composition.alias(handle);
         }
-        .into()
+        composition
     }
 
     fn handle(&self) -> &str {
@@ -81,17 +401,62 @@ impl Config for PostgresServerConfig {
     }
 }
 
+/// Connects to the given URL, driving the connection in the background.
+///
+/// The connection is made over TLS when a connector is given.
+async fn connect(
+    url: &str,
+    tls: Option<MakeRustlsConnect>,
+) -> Result<Client, tokio_postgres::Error> {
+    let client = match tls {
+        Some(tls) => {
+            let (client, connection) = tokio_postgres::connect(url, tls).await?;
+            tokio::spawn(connection);
+            client
+        }
+        None => {
+            let (client, connection) = tokio_postgres::connect(url, NoTls).await?;
+            tokio::spawn(connection);
+            client
+        }
+    };
+    Ok(client)
+}
+
+/// Returns a database which can be connected to while managing the given one.
+fn maintenance_database(database: &str) -> &str {
+    match database {
+        "postgres" => "template1",
+        _ => "postgres",
+    }
+}
+
+/// Quotes the given identifier.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
 /// A running instance of a PostgreSQL server.
 ///
-/// The server address which is accessible from the local host can be found in
-/// `local_address`. Other running containers which need access to this server
-/// should use the `address` field instead.
+/// The `database` field contains the name of the database created on startup,
+/// which is included in the URLs returned by `external_auth_url` and
+/// `internal_auth_url`. The server address which is accessible from the local
+/// host can be found with `external_address`. Other running containers which
+/// need access to this server should use `internal_address` instead.
+///
+/// When TLS is enabled the `tls` field contains the paths to the generated
+/// certificates and the URLs returned by `external_tls_url` and
+/// `internal_tls_url` verify the server certificate against them.
 pub struct PostgresServer {
+    pub database: String,
     pub external_port: u32,
+    pub handle: String,
     pub internal_port: u32,
     pub ip: String,
     pub password: String,
+    pub tls: Option<PostgresTls>,
     pub username: String,
+    connector: Option<MakeRustlsConnect>,
 }
 
 impl PostgresServer {
@@ -100,14 +465,33 @@ impl PostgresServer {
     }
 
     fn format_auth_url(&self, host: &str, port: u32) -> String {
+        self.format_database_url(host, port, &self.database)
+    }
+
+    fn format_database_url(&self, host: &str, port: u32, database: &str) -> String {
         format!(
-            "postgresql://{}:{}@{}",
+            "postgresql://{}:{}@{}/{}",
             self.username,
             self.password,
-            self.format_address(host, port)
+            self.format_address(host, port),
+            database
         )
     }
 
+    fn format_tls_url(&self, host: &str, port: u32) -> Option<String> {
+        self.tls.as_ref().map(|tls| {
+            let mut url = format!(
+                "{}?sslmode=verify-full&sslrootcert={}",
+                self.format_auth_url(host, port),
+                tls.ca_cert
+            );
+            if let (Some(cert), Some(key)) = (&tls.client_cert, &tls.client_key) {
+                url.push_str(&format!("&sslcert={}&sslkey={}", cert, key));
+            }
+            url
+        })
+    }
+
     fn format_url(&self, host: &str, port: u32) -> String {
         format!("postgresql://{}", self.format_address(host, port))
     }
@@ -122,6 +506,18 @@ impl PostgresServer {
         self.format_auth_url("localhost", self.external_port)
     }
 
+    /// The external libpq URL for the given database with the
+    /// username/password embedded in the URL
+    pub fn external_database_url(&self, database: &str) -> String {
+        self.format_database_url("localhost", self.external_port, database)
+    }
+
+    /// The external libpq URL with the username/password and TLS parameters
+    /// embedded in the URL, if TLS is enabled
+    pub fn external_tls_url(&self) -> Option<String> {
+        self.format_tls_url("localhost", self.external_port)
+    }
+
     /// The external libpq URL
     pub fn external_url(&self) -> String {
         self.format_url("localhost", self.external_port)
@@ -137,30 +533,393 @@ impl PostgresServer {
         self.format_auth_url(self.ip.as_str(), self.internal_port)
     }
 
+    /// The internal libpq URL for the given database with the
+    /// username/password embedded in the URL
+    pub fn internal_database_url(&self, database: &str) -> String {
+        self.format_database_url(self.ip.as_str(), self.internal_port, database)
+    }
+
+    /// The internal libpq URL with the username/password and TLS parameters
+    /// embedded in the URL, if TLS is enabled
+    ///
+    /// The server is addressed by its handle so that its certificate can be
+    /// verified. Note that the certificate paths refer to the local host.
+    pub fn internal_tls_url(&self) -> Option<String> {
+        self.format_tls_url(self.handle.as_str(), self.internal_port)
+    }
+
     /// The internal libpq URL
     pub fn internal_url(&self) -> String {
         self.format_url(self.ip.as_str(), self.internal_port)
     }
+
+    /// Connects to the database used for creating and dropping databases.
+    async fn maintenance_client(&self) -> Result<Client, tokio_postgres::Error> {
+        connect(
+            &self.external_database_url(maintenance_database(&self.database)),
+            self.connector.clone(),
+        )
+        .await
+    }
+
+    /// Creates a new database with the given name using the database created
+    /// on startup as its template.
+    ///
+    /// A database can't be copied while other sessions are connected to it, so
+    /// any existing connections to the template are terminated first. Returns
+    /// the external URL of the new database.
+    pub async fn create_database_from_template(
+        &self,
+        name: &str,
+    ) -> Result<String, tokio_postgres::Error> {
+        let client = self.maintenance_client().await?;
+        client
+            .execute(
+                "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+                 WHERE datname = $1 AND pid <> pg_backend_pid()",
+                &[&self.database],
+            )
+            .await?;
+        client
+            .execute(
+                format!(
+                    "CREATE DATABASE {} TEMPLATE {}",
+                    quote(name),
+                    quote(&self.database)
+                )
+                .as_str(),
+                &[],
+            )
+            .await?;
+
+        Ok(self.external_database_url(name))
+    }
+
+    /// Drops the database with the given name, terminating any connections to
+    /// it.
+    pub async fn drop_database(&self, name: &str) -> Result<(), tokio_postgres::Error> {
+        let client = self.maintenance_client().await?;
+        client
+            .execute(
+                "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+                 WHERE datname = $1 AND pid <> pg_backend_pid()",
+                &[&name],
+            )
+            .await?;
+        client
+            .execute(
+                format!("DROP DATABASE IF EXISTS {}", quote(name)).as_str(),
+                &[],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Promotes this server to a primary if it's a replica of another server.
+    ///
+    /// Returns whether the promotion completed within the default wait period
+    /// of `pg_promote`.
+    pub async fn promote(&self) -> Result<bool, tokio_postgres::Error> {
+        let client = connect(&self.external_auth_url(), self.connector.clone()).await?;
+        let promoted = client.query_one("SELECT pg_promote()", &[]).await?.get(0);
+        Ok(promoted)
+    }
+
+    /// Runs the given closure against a fresh copy of the template database.
+    ///
+    /// The closure receives the external URL of a randomly named database
+    /// created with `create_database_from_template`, which is dropped once the
+    /// closure returns. The database is also dropped if the closure panics,
+    /// after which the panic is resumed.
+    pub async fn with_database<F, Fut, T>(&self, fun: F) -> Result<T, tokio_postgres::Error>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let name = format!("{}_{}", self.database, rand_string(10).to_lowercase());
+        let url = self.create_database_from_template(&name).await?;
+        let result = AssertUnwindSafe(fun(url)).catch_unwind().await;
+        let dropped = self.drop_database(&name).await;
+        match result {
+            Ok(result) => dropped.map(|_| result),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
 }
 
 impl Server for PostgresServer {
     type Config = PostgresServerConfig;
 
     fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        let state = config.state.lock().unwrap();
         PostgresServer {
+            database: config.database.clone(),
             external_port: config.port,
+            handle: config.handle.clone(),
             internal_port: PORT,
             ip: container.ip().to_string(),
             password: config.password.clone(),
-            username: USER.to_string(),
+            tls: state.tls.clone(),
+            username: config.username.clone(),
+            connector: state.connector.clone(),
+        }
+    }
+}
+
+/// Configuration for creating a PostgreSQL primary with streaming replicas.
+///
+/// The primary is created using the `primary` field and `replicas` hot standby
+/// servers are created from it using `pg_basebackup`. Each replica uses the
+/// same credentials, database, flavor and version as the primary and is only
+/// considered ready once it has replayed all changes made on the primary up
+/// until the point it was brought up.
+///
+/// The replicas listen on port 5432 for requests. These are exposed on the
+/// containers starting at the port given by the `replica_port` field and
+/// increasing by one for each replica.
+///
+/// The replicas connect to the primary without TLS, so requiring client
+/// certificates on the primary is not supported and fails to build. A replica
+/// can be promoted to a primary using `promote` on its [PostgresServer].
+#[derive(Clone, Default, Builder)]
+#[builder(default, build_fn(validate = "Self::validate"))]
+pub struct PostgresReplicationConfig {
+    #[builder(default = "PostgresServerConfig::builder().build().unwrap()")]
+    pub primary: PostgresServerConfig,
+    #[builder(default = "8201")]
+    pub replica_port: u32,
+    #[builder(default = "1")]
+    pub replicas: u8,
+    #[builder(default = "60")]
+    pub timeout: u16,
+    #[builder(setter(skip))]
+    state: Arc<Mutex<PostgresReplicationState>>,
+}
+
+impl PostgresReplicationConfigBuilder {
+    fn validate(&self) -> Result<(), String> {
+        match &self.primary {
+            Some(primary) if primary.client_certs => Err(String::from(
+                "client certificates are not supported with replication",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Data generated while a [PostgresReplicationConfig] is brought up.
+#[derive(Default)]
+struct PostgresReplicationState {
+    files: Vec<TempPath>,
+    ips: HashMap<String, String>,
+}
+
+impl PostgresReplicationConfig {
+    pub fn builder() -> PostgresReplicationConfigBuilder {
+        PostgresReplicationConfigBuilder::default()
+    }
+
+    /// The handle of the replica at the given index
+    fn replica_handle(&self, index: u8) -> String {
+        format!("{}-replica-{}", self.primary.handle, index)
+    }
+
+    /// Writes the given script to a temporary file and returns its path.
+    fn write_script(&self, name: &str, content: &str) -> Result<String, String> {
+        let file = write_tempfile(name, ".sh", content.as_bytes(), 0o755)
+            .map_err(|e| format!("failed writing replication script: {}", e))?;
+        let path = file.to_string_lossy().to_string();
+        self.state.lock().unwrap().files.push(file);
+        Ok(path)
+    }
+
+    /// Waits for the replica at the given index to catch up with the primary.
+    async fn replica_ready(self, index: u8, ip: String) -> Result<(), String> {
+        let primary = &self.primary;
+        if primary.client_certs {
+            return Err(String::from(
+                "client certificates are not supported with replication",
+            ));
+        }
+        let url = |port: u32| {
+            format!(
+                "postgresql://{}:{}@localhost:{}/{}",
+                primary.username, primary.password, port, primary.database
+            )
+        };
+        let (primary_url, replica_url) =
+            (url(primary.port), url(self.replica_port + u32::from(index)));
+
+        let client = retry(self.timeout, || async {
+            connect(&primary_url, None).await.map_err(|e| e.to_string())
+        })
+        .await?;
+        let lsn: String = client
+            .query_one("SELECT pg_current_wal_lsn()::text", &[])
+            .await
+            .map_err(|e| e.to_string())?
+            .get(0);
+
+        retry(self.timeout, || async {
+            let client = connect(&replica_url, None)
+                .await
+                .map_err(|e| e.to_string())?;
+            let caught_up: bool = client
+                .query_one(
+                    "SELECT pg_is_in_recovery() AND pg_last_wal_replay_lsn() >= $1::text::pg_lsn",
+                    &[&lsn],
+                )
+                .await
+                .map_err(|e| e.to_string())?
+                .get(0);
+            match caught_up {
+                true => Ok(()),
+                false => Err(String::from("replica has not caught up")),
+            }
+        })
+        .await?;
+
+        self.state
+            .lock()
+            .unwrap()
+            .ips
+            .insert(self.replica_handle(index), ip);
+        Ok(())
+    }
+}
+
+impl Config for PostgresReplicationConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        self.into_compositions().remove(0)
+    }
+
+    fn into_compositions(self) -> Vec<dockertest::Composition> {
+        let scripts = self
+            .write_script("replication-hba", include_str!("./replication-hba.sh"))
+            .and_then(|hba| {
+                self.write_script("replica", include_str!("./replica.sh"))
+                    .map(|replica| (hba, replica))
+            });
+
+        // Failing to write either script fails the test
+        let mut primary = self.primary.clone();
+        let (script, failed) = match scripts {
+            Ok((hba, script)) => {
+                primary.init_scripts.push(PostgresInitScript::File(hba));
+                (script, None)
+            }
+            Err(e) => (String::new(), Some(e)),
+        };
+
+        // Replicas connect to the primary using its handle
+        let mut compositions = vec![match &failed {
+            Some(e) => primary
+                .into_composition()
+                .with_wait_for(Box::new(HookWait::fail(e.clone()))),
+            None => primary.into_composition(),
+        }];
+        if !self.primary.tls {
+            compositions[0].alias(self.primary.handle.clone());
+        }
+
+        let mut env = self.primary.env.clone();
+        env.insert(String::from("POSTGRES_USER"), self.primary.username.clone());
+        env.insert(
+            String::from("POSTGRES_PASSWORD"),
+            self.primary.password.clone(),
+        );
+        env.insert(String::from("PRIMARY_HOST"), self.primary.handle.clone());
+
+        for index in 0..self.replicas {
+            let config = self.clone();
+            let (bind_mounts, wait) = match &failed {
+                Some(e) => (HashMap::new(), HookWait::fail(e.clone())),
+                None => (
+                    HashMap::from([(String::from("/replica.sh"), script.clone())]),
+                    HookWait::running(self.timeout, move |container| {
+                        config
+                            .clone()
+                            .replica_ready(index, container.ip().to_string())
+                    }),
+                ),
+            };
+
+            let mut args = vec![String::from("bash"), String::from("/replica.sh")];
+            args.extend(self.primary.args.clone());
+
+            compositions.push(
+                ContainerConfig {
+                    args,
+                    env: env.clone(),
+                    handle: self.replica_handle(index),
+                    name: self.primary.flavor.image().into(),
+                    source: SOURCE,
+                    version: self.primary.version.clone(),
+                    ports: Some(vec![(PORT, self.replica_port + u32::from(index))]),
+                    wait: Some(Box::new(wait)),
+                    bind_mounts,
+                }
+                .into(),
+            );
+        }
+
+        compositions
+    }
+
+    fn handle(&self) -> &str {
+        self.primary.handle.as_str()
+    }
+}
+
+/// A running PostgreSQL primary along with its streaming replicas.
+///
+/// The `replicas` field contains each replica in the order they were created.
+/// Every server shares the credentials and database of the primary.
+pub struct PostgresReplication {
+    pub primary: PostgresServer,
+    pub replicas: Vec<PostgresServer>,
+}
+
+impl Server for PostgresReplication {
+    type Config = PostgresReplicationConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        let state = config.state.lock().unwrap();
+        let replicas = (0..config.replicas)
+            .map(|index| PostgresServer {
+                database: config.primary.database.clone(),
+                external_port: config.replica_port + u32::from(index),
+                handle: config.replica_handle(index),
+                internal_port: PORT,
+                ip: state
+                    .ips
+                    .get(&config.replica_handle(index))
+                    .cloned()
+                    .unwrap_or_default(),
+                password: config.primary.password.clone(),
+                tls: None,
+                username: config.primary.username.clone(),
+                connector: None,
+            })
+            .collect();
+
+        PostgresReplication {
+            primary: PostgresServer::new(&config.primary, container),
+            replicas,
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{PostgresServer, PostgresServerConfig};
+    use super::{
+        PostgresFlavor, PostgresInitScript, PostgresReplication, PostgresReplicationConfig,
+        PostgresServer, PostgresServerConfig,
+    };
+    use crate::servers::database::tls::MakeRustlsConnect;
     use crate::Test;
+    use futures::FutureExt;
+    use std::panic::AssertUnwindSafe;
     use test_log::test;
     use tokio_postgres::NoTls;
 
@@ -178,4 +937,231 @@ mod tests {
             assert!(res.is_ok())
         });
     }
+
+    #[test]
+    fn test_postgres_init() {
+        let config = PostgresServerConfig::builder()
+            .database("app".into())
+            .init_scripts(vec![
+                PostgresInitScript::Sql("CREATE TABLE users (name TEXT);".into()),
+                PostgresInitScript::Sql("INSERT INTO users VALUES ('test');".into()),
+            ])
+            .port(PORT + 1)
+            .username("app".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: PostgresServer = instance.server();
+            assert!(server.external_auth_url().ends_with("/app"));
+
+            let (client, connection) =
+                tokio_postgres::connect(server.external_auth_url().as_str(), NoTls)
+                    .await
+                    .unwrap();
+            tokio::spawn(connection);
+
+            let row = client
+                .query_one("SELECT name FROM users", &[])
+                .await
+                .unwrap();
+            assert_eq!(row.get::<_, &str>(0), "test");
+        });
+    }
+
+    #[test]
+    fn test_postgres_template() {
+        let config = PostgresServerConfig::builder()
+            .database("app".into())
+            .init_scripts(vec![PostgresInitScript::Sql(
+                "CREATE TABLE users (name TEXT);".into(),
+            )])
+            .port(PORT + 2)
+            .template(true)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: PostgresServer = instance.server();
+
+            for _ in 0..2 {
+                let count = server
+                    .with_database(|url| async move {
+                        let (client, connection) =
+                            tokio_postgres::connect(&url, NoTls).await.unwrap();
+                        tokio::spawn(connection);
+
+                        client
+                            .execute("INSERT INTO users VALUES ('test')", &[])
+                            .await
+                            .unwrap();
+                        client
+                            .query_one("SELECT COUNT(*) FROM users", &[])
+                            .await
+                            .unwrap()
+                            .get::<_, i64>(0)
+                    })
+                    .await
+                    .unwrap();
+                assert_eq!(count, 1);
+            }
+
+            // The copy is dropped even if the closure panics
+            let res = AssertUnwindSafe(server.with_database(|_| async move { panic!("failed") }))
+                .catch_unwind()
+                .await;
+            assert!(res.is_err());
+
+            let (client, connection) =
+                tokio_postgres::connect(&server.external_database_url("postgres"), NoTls)
+                    .await
+                    .unwrap();
+            tokio::spawn(connection);
+            let count = client
+                .query_one(
+                    "SELECT COUNT(*) FROM pg_database WHERE datname LIKE 'app\\_%'",
+                    &[],
+                )
+                .await
+                .unwrap()
+                .get::<_, i64>(0);
+            assert_eq!(count, 0);
+        });
+    }
+
+    #[test]
+    fn test_postgres_tls() {
+        let config = PostgresServerConfig::builder()
+            .client_certs(true)
+            .port(PORT + 5)
+            .tls(true)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: PostgresServer = instance.server();
+            let tls = server.tls.clone().unwrap();
+            assert!(server
+                .external_tls_url()
+                .unwrap()
+                .contains("sslmode=verify-full"));
+
+            let res = tokio_postgres::connect(server.external_auth_url().as_str(), NoTls).await;
+            assert!(res.is_err());
+
+            let read = |path: &Option<String>| std::fs::read_to_string(path.as_ref().unwrap());
+            let connector = MakeRustlsConnect::new(
+                &std::fs::read_to_string(&tls.ca_cert).unwrap(),
+                Some((
+                    &read(&tls.client_cert).unwrap(),
+                    &read(&tls.client_key).unwrap(),
+                )),
+            )
+            .unwrap();
+            let (client, connection) =
+                tokio_postgres::connect(server.external_auth_url().as_str(), connector)
+                    .await
+                    .unwrap();
+            tokio::spawn(connection);
+
+            let ssl: bool = client
+                .query_one(
+                    "SELECT ssl FROM pg_stat_ssl WHERE pid = pg_backend_pid()",
+                    &[],
+                )
+                .await
+                .unwrap()
+                .get(0);
+            assert!(ssl);
+        });
+    }
+
+    #[test]
+    fn test_postgres_flavor() {
+        let config = PostgresServerConfig::builder()
+            .extensions(vec!["vector".into()])
+            .flavor(PostgresFlavor::PgVector)
+            .port(PORT + 6)
+            .build()
+            .unwrap();
+        assert_eq!(config.version, "pg17");
+
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: PostgresServer = instance.server();
+            let (client, connection) =
+                tokio_postgres::connect(server.external_auth_url().as_str(), NoTls)
+                    .await
+                    .unwrap();
+            tokio::spawn(connection);
+
+            let row = client
+                .query_one("SELECT '[1,2,3]'::vector <-> '[1,2,4]'::vector", &[])
+                .await
+                .unwrap();
+            assert_eq!(row.get::<_, f64>(0), 1.0);
+        });
+    }
+
+    #[test]
+    fn test_replication_client_certs() {
+        let primary = PostgresServerConfig::builder()
+            .client_certs(true)
+            .tls(true)
+            .build()
+            .unwrap();
+        let res = PostgresReplicationConfig::builder()
+            .primary(primary)
+            .build();
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_postgres_replication() {
+        let primary = PostgresServerConfig::builder()
+            .port(PORT + 3)
+            .build()
+            .unwrap();
+        let config = PostgresReplicationConfig::builder()
+            .primary(primary)
+            .replica_port(PORT + 4)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: PostgresReplication = instance.server();
+
+            let (primary, connection) =
+                tokio_postgres::connect(&server.primary.external_auth_url(), NoTls)
+                    .await
+                    .unwrap();
+            tokio::spawn(connection);
+            primary
+                .execute("CREATE TABLE test (id INT)", &[])
+                .await
+                .unwrap();
+
+            let (replica, connection) =
+                tokio_postgres::connect(&server.replicas[0].external_auth_url(), NoTls)
+                    .await
+                    .unwrap();
+            tokio::spawn(connection);
+            let res = replica.execute("INSERT INTO test VALUES (1)", &[]).await;
+            assert!(res.is_err());
+
+            assert!(server.replicas[0].promote().await.unwrap());
+            let res = replica.execute("INSERT INTO test VALUES (1)", &[]).await;
+            assert!(res.is_ok());
+        });
+    }
 }
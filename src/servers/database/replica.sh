@@ -0,0 +1,32 @@
+#!/bin/bash
+# Clones the primary into an empty data directory and starts a hot standby
+set -e
+
+# Commands are run as the postgres user, switching to it when started as root
+as_postgres=()
+if [ "$(id -u)" = "0" ]; then
+    if command -v gosu > /dev/null; then
+        as_postgres=(gosu postgres)
+    elif command -v su-exec > /dev/null; then
+        as_postgres=(su-exec postgres)
+    else
+        echo "neither gosu nor su-exec is available" >&2
+        exit 1
+    fi
+fi
+
+until pg_isready -q -h "$PRIMARY_HOST" -U "$POSTGRES_USER"; do
+    sleep 1
+done
+
+mkdir -p "$PGDATA"
+if [ "$(id -u)" = "0" ]; then
+    chown postgres:postgres "$PGDATA"
+fi
+chmod 700 "$PGDATA"
+
+"${as_postgres[@]}" pg_basebackup \
+    -d "host=$PRIMARY_HOST user=$POSTGRES_USER password=$POSTGRES_PASSWORD" \
+    -D "$PGDATA" -R -X stream
+
+exec "${as_postgres[@]}" postgres -c listen_addresses='*' "$@"
@@ -0,0 +1,6 @@
+#!/bin/bash
+# Allows replication connections using the same method as other connections
+set -e
+
+line=$(sed -n 's/^host all all all/host replication all all/p' "$PGDATA/pg_hba.conf")
+echo "$line" >> "$PGDATA/pg_hba.conf"
@@ -0,0 +1,141 @@
+/// Contains a rustls based TLS connector for tokio-postgres.
+use futures::future::BoxFuture;
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerName};
+use std::convert::TryFrom;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_postgres::tls::{ChannelBinding, MakeTlsConnect, TlsConnect, TlsStream};
+
+/// Creates TLS connections which trust the given CA and optionally present a
+/// client certificate.
+#[derive(Clone)]
+pub(crate) struct MakeRustlsConnect {
+    config: Arc<ClientConfig>,
+}
+
+impl MakeRustlsConnect {
+    /// Creates a new connector from the given PEM encoded CA certificate and
+    /// client certificate/key pair.
+    pub(crate) fn new(ca_pem: &str, client: Option<(&str, &str)>) -> io::Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for cert in rustls_pemfile::certs(&mut ca_pem.as_bytes())? {
+            roots
+                .add(&Certificate(cert))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let config = match client {
+            Some((cert_pem, key_pem)) => {
+                let certs = rustls_pemfile::certs(&mut cert_pem.as_bytes())?
+                    .into_iter()
+                    .map(Certificate)
+                    .collect();
+                let key = rustls_pemfile::pkcs8_private_keys(&mut key_pem.as_bytes())?
+                    .pop()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing key"))?;
+                builder
+                    .with_client_auth_cert(certs, PrivateKey(key))
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(MakeRustlsConnect {
+            config: Arc::new(config),
+        })
+    }
+}
+
+impl<S> MakeTlsConnect<S> for MakeRustlsConnect
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = RustlsStream<S>;
+    type TlsConnect = RustlsConnect;
+    type Error = io::Error;
+
+    fn make_tls_connect(&mut self, domain: &str) -> io::Result<RustlsConnect> {
+        let name = ServerName::try_from(domain)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        Ok(RustlsConnect {
+            config: self.config.clone(),
+            name,
+        })
+    }
+}
+
+/// Creates a single TLS connection to a server.
+pub(crate) struct RustlsConnect {
+    config: Arc<ClientConfig>,
+    name: ServerName,
+}
+
+impl<S> TlsConnect<S> for RustlsConnect
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = RustlsStream<S>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<RustlsStream<S>>>;
+
+    fn connect(self, stream: S) -> Self::Future {
+        Box::pin(async move {
+            let stream = tokio_rustls::TlsConnector::from(self.config)
+                .connect(self.name, stream)
+                .await?;
+            Ok(RustlsStream(stream))
+        })
+    }
+}
+
+/// A TLS connection to a server.
+pub(crate) struct RustlsStream<S>(tokio_rustls::client::TlsStream<S>);
+
+impl<S> AsyncRead for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl<S> TlsStream for RustlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn channel_binding(&self) -> ChannelBinding {
+        ChannelBinding::none()
+    }
+}
@@ -0,0 +1,11 @@
+#!/bin/bash
+# Copies the mounted TLS material into a directory owned by the server with
+# the permissions it requires and then hands off to the image entrypoint
+set -e
+
+mkdir -p /etc/postgresql/tls
+cp /tls/* /etc/postgresql/tls/
+chown -R postgres:postgres /etc/postgresql/tls
+chmod 600 /etc/postgresql/tls/server.key
+
+exec docker-entrypoint.sh "$@"
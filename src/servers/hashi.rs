@@ -1,7 +1,12 @@
 /// Contains [Servers][Server] for Hashicorp products.
+pub mod boundary;
 pub mod consul;
 pub mod counting;
+pub mod nomad;
 pub mod vault;
 
-pub use consul::{ConsulServer, ConsulServerConfig};
-pub use vault::{VaultServer, VaultServerConfig};
+pub use boundary::{BoundaryServer, BoundaryServerConfig};
+pub use consul::{ConsulCluster, ConsulClusterConfig, ConsulServer, ConsulServerConfig};
+pub use counting::{CountingServer, CountingServerConfig};
+pub use nomad::{NomadServer, NomadServerConfig};
+pub use vault::{VaultCluster, VaultClusterConfig, VaultServer, VaultServerConfig};
@@ -0,0 +1,265 @@
+use crate::waitfor::{retry, HookWait};
+use crate::{Config, ContainerConfig, Server};
+use derive_builder::Builder;
+use dockertest::{waitfor, Source};
+use std::collections::HashMap;
+
+const DATABASE_IMAGE: &str = "postgres";
+const DATABASE_LOG_MSG: &str = "listening on IPv4 address \"0.0.0.0\", port 5432";
+const DATABASE_PASSWORD: &str = "boundary";
+const IMAGE: &str = "hashicorp/boundary";
+const PORT: u32 = 9200;
+const SOURCE: Source = Source::DockerHub;
+
+/// Configuration for creating a Hashicorp Boundary server.
+///
+/// The server is started in development mode, which runs a controller and a
+/// worker in the same container and generates a set of default resources
+/// including a password auth method and an admin login for it. Development
+/// mode normally starts its own Postgres container, so instead a Postgres
+/// container is brought up alongside the server and the server is started
+/// once it accepts connections.
+///
+/// By default the Boundary server listens on port 9200 for API requests. This
+/// is exposed on the container by default, but the exposed port can be
+/// controlled by setting the `port` field.
+///
+/// See the [Dockerhub](https://hub.docker.com/r/hashicorp/boundary) page for
+/// more information on the arguments and environment variables that can be
+/// used to configure the server.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct BoundaryServerConfig {
+    #[builder(default = "Vec::new()")]
+    pub args: Vec<String>,
+    #[builder(default = "String::from(\"ampw_1234567890\")")]
+    pub auth_method_id: String,
+    #[builder(default = "String::from(\"15\")")]
+    pub database_version: String,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "String::from(\"admin\")")]
+    pub login_name: String,
+    #[builder(default = "String::from(\"password\")")]
+    pub password: String,
+    #[builder(default = "9200")]
+    pub port: u32,
+    #[builder(default = "30")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"0.13.2\")")]
+    pub version: String,
+}
+
+impl BoundaryServerConfig {
+    pub fn builder() -> BoundaryServerConfigBuilder {
+        BoundaryServerConfigBuilder::default()
+    }
+
+    /// The handle of the Postgres container
+    fn database_handle(&self) -> String {
+        format!("{}-postgres", self.handle)
+    }
+
+    /// Waits for the API to list the auth methods of the global scope, which
+    /// is allowed for anonymous users once the server is initialized.
+    async fn ready(self) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!(
+            "http://localhost:{}/v1/auth-methods?scope_id=global",
+            self.port
+        );
+        retry(self.timeout, || async {
+            client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .await
+    }
+}
+
+impl Config for BoundaryServerConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        self.into_compositions().remove(1)
+    }
+
+    fn into_compositions(self) -> Vec<dockertest::Composition> {
+        let database_handle = self.database_handle();
+        let env = HashMap::from([(
+            String::from("POSTGRES_PASSWORD"),
+            String::from(DATABASE_PASSWORD),
+        )]);
+        let wait = Box::new(waitfor::MessageWait {
+            message: DATABASE_LOG_MSG.into(),
+            source: waitfor::MessageSource::Stderr,
+            timeout: self.timeout,
+        });
+
+        // The server connects to the database using its handle
+        let mut database: dockertest::Composition = ContainerConfig {
+            args: Vec::new(),
+            env,
+            handle: database_handle.clone(),
+            name: DATABASE_IMAGE.into(),
+            source: SOURCE,
+            version: self.database_version.clone(),
+            ports: None,
+            wait: Some(wait),
+            bind_mounts: HashMap::new(),
+        }
+        .into();
+        database.alias(database_handle.clone());
+
+        let mut args = vec![
+            String::from("dev"),
+            format!("-api-listen-address=0.0.0.0:{}", PORT),
+            format!(
+                "-database-url=postgresql://postgres:{}@{}:5432/postgres?sslmode=disable",
+                DATABASE_PASSWORD, database_handle
+            ),
+            format!("-login-name={}", self.login_name),
+            format!("-password={}", self.password),
+            format!("-password-auth-method-id={}", self.auth_method_id),
+        ];
+        args.extend(self.args.clone());
+
+        let config = self.clone();
+        let wait = Box::new(HookWait::running(self.timeout, move |_| {
+            config.clone().ready()
+        }));
+
+        let server: dockertest::Composition = ContainerConfig {
+            args,
+            env: self.env,
+            handle: self.handle,
+            name: IMAGE.into(),
+            source: SOURCE,
+            version: self.version,
+            ports: Some(vec![(PORT, self.port)]),
+            wait: Some(wait),
+            bind_mounts: HashMap::new(),
+        }
+        .into();
+
+        // The database must be accepting connections before the server starts
+        vec![
+            database.with_start_policy(dockertest::StartPolicy::Strict),
+            server.with_start_policy(dockertest::StartPolicy::Strict),
+        ]
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// A running instance of a Boundary server.
+///
+/// The `auth_method_id`, `login_name` and `password` fields contain the
+/// credentials of the administrator generated by development mode. The API URL
+/// which is accessible from the local host can be found with `external_url`.
+/// Other running containers which need access to this server should use
+/// `internal_url` instead.
+pub struct BoundaryServer {
+    pub auth_method_id: String,
+    pub external_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+    pub login_name: String,
+    pub password: String,
+}
+
+impl BoundaryServer {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost::{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external HTTP address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal HTTP address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+impl Server for BoundaryServer {
+    type Config = BoundaryServerConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        BoundaryServer {
+            auth_method_id: config.auth_method_id.clone(),
+            external_port: config.port,
+            internal_port: PORT,
+            ip: container.ip().to_string(),
+            login_name: config.login_name.clone(),
+            password: config.password.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{BoundaryServer, BoundaryServerConfig};
+    use crate::Test;
+    use serde_json::{json, Value};
+
+    const PORT: u32 = 9280;
+
+    #[test]
+    fn test_boundary() {
+        let config = BoundaryServerConfig::builder().port(PORT).build().unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: BoundaryServer = instance.server();
+
+            let client = reqwest::Client::new();
+            let resp: Value = client
+                .post(format!(
+                    "{}/v1/auth-methods/{}:authenticate",
+                    server.external_url(),
+                    server.auth_method_id
+                ))
+                .json(&json!({
+                    "command": "login",
+                    "attributes": {
+                        "login_name": server.login_name,
+                        "password": server.password,
+                    },
+                }))
+                .send()
+                .await
+                .unwrap()
+                .error_for_status()
+                .unwrap()
+                .json()
+                .await
+                .unwrap();
+            assert!(resp["attributes"]["token"].is_string());
+        });
+    }
+}
@@ -1,19 +1,101 @@
-use crate::common::rand_string;
+use crate::common::rand_uuid;
+use crate::waitfor::{retry, HookWait};
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
 use dockertest::{waitfor, Source};
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 const IMAGE: &str = "consul";
 const PORT: u32 = 8500;
+const DNS_PORT: u32 = 8600;
+const GRPC_PORT: u32 = 8502;
 const LOG_MSG: &str = "Synced node info";
 const SOURCE: Source = Source::DockerHub;
 
+/// A health check attached to a [ConsulService].
+///
+/// The interval is a duration string (i.e. `10s`).
+#[derive(Clone)]
+pub enum ConsulCheck {
+    Http { url: String, interval: String },
+    Tcp { address: String, interval: String },
+    Ttl(String),
+}
+
+impl ConsulCheck {
+    fn to_json(&self) -> Value {
+        match self {
+            ConsulCheck::Http { url, interval } => json!({ "HTTP": url, "Interval": interval }),
+            ConsulCheck::Tcp { address, interval } => {
+                json!({ "TCP": address, "Interval": interval })
+            }
+            ConsulCheck::Ttl(ttl) => json!({ "TTL": ttl }),
+        }
+    }
+}
+
+/// A service to register with a Consul server.
+///
+/// If the `id` is omitted the `name` of the service is used instead.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct ConsulService {
+    #[builder(default = "String::new()", setter(into))]
+    pub address: String,
+    #[builder(default, setter(into, strip_option))]
+    pub check: Option<ConsulCheck>,
+    #[builder(default, setter(into, strip_option))]
+    pub id: Option<String>,
+    #[builder(default = "String::new()", setter(into))]
+    pub name: String,
+    #[builder(default = "0")]
+    pub port: u32,
+    #[builder(default = "Vec::new()")]
+    pub tags: Vec<String>,
+}
+
+impl ConsulService {
+    pub fn builder() -> ConsulServiceBuilder {
+        ConsulServiceBuilder::default()
+    }
+
+    fn to_json(&self) -> Value {
+        let mut service = json!({
+            "ID": self.id.as_ref().unwrap_or(&self.name),
+            "Name": self.name,
+            "Address": self.address,
+            "Port": self.port,
+            "Tags": self.tags,
+        });
+        if let Some(check) = &self.check {
+            service["Check"] = check.to_json();
+        }
+        service
+    }
+}
+
 /// Configuration for creating a Hashicorp Consul server.
 ///
 /// By default the Consul server listens on port 8500 for HTTP requests. This
 /// is exposed on the container by default, but the exposed port can be
-/// controlled by setting the `port` field.
+/// controlled by setting the `port` field. The DNS interface listens on port
+/// 8600 and is only exposed when the `dns_port` field is set. Note that only
+/// TCP ports are exposed, so DNS queries from the local host must be made over
+/// TCP.
+///
+/// The entries in the `kv` field are written to the KV store and the services
+/// in the `services` field are registered with the agent before the server is
+/// considered ready.
+///
+/// If `acl` is set the ACL system is enabled with a default policy of `deny`
+/// and the `token` field is installed as the initial management token. If the
+/// token is omitted it will automatically be generated. Note that Consul
+/// requires the token to be a UUID. Versions prior to 1.11 name this token the
+/// master token, which is used instead when `version` is one of them. The ACL
+/// configuration is merged into any JSON configuration given through the
+/// `CONSUL_LOCAL_CONFIG` environment variable.
 ///
 /// See the [Dockerhub](https://hub.docker.com/_/consul) page for more
 /// information on the arguments and environment variables that can be used to
@@ -21,17 +103,25 @@ const SOURCE: Source = Source::DockerHub;
 #[derive(Clone, Default, Builder)]
 #[builder(default)]
 pub struct ConsulServerConfig {
+    #[builder(default = "false")]
+    pub acl: bool,
     #[builder(default = "Vec::new()")]
     pub args: Vec<String>,
+    #[builder(default, setter(strip_option))]
+    pub dns_port: Option<u32>,
     #[builder(default = "HashMap::new()")]
     pub env: HashMap<String, String>,
     #[builder(default = "crate::server::new_handle(IMAGE)")]
     pub handle: String,
+    #[builder(default = "HashMap::new()")]
+    pub kv: HashMap<String, String>,
     #[builder(default = "9500")]
     pub port: u32,
+    #[builder(default = "Vec::new()")]
+    pub services: Vec<ConsulService>,
     #[builder(default = "15")]
     pub timeout: u16,
-    #[builder(default = "rand_string(16)")]
+    #[builder(default = "rand_uuid()")]
     pub token: String,
     #[builder(default = "String::from(\"latest\")")]
     pub version: String,
@@ -41,22 +131,101 @@ impl ConsulServerConfig {
     pub fn builder() -> ConsulServerConfigBuilder {
         ConsulServerConfigBuilder::default()
     }
+
+    /// Returns the agent configuration which enables and bootstraps ACLs.
+    fn acl_config(&self) -> Value {
+        // The initial management token was named the master token before 1.11
+        let version: Vec<u32> = self
+            .version
+            .split('.')
+            .take(2)
+            .map_while(|v| v.parse().ok())
+            .collect();
+        let management = match version.as_slice() {
+            [major, minor] if (*major, *minor) < (1, 11) => "master",
+            _ => "initial_management",
+        };
+
+        json!({
+            "acl": {
+                "enabled": true,
+                "default_policy": "deny",
+                "tokens": {
+                    management: self.token,
+                    "agent": self.token,
+                },
+            },
+        })
+    }
+
+    /// Writes the configured KV entries and registers the configured services.
+    async fn seed(self) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{}/v1", self.port);
+        let request = |path: String| {
+            let request = client.put(format!("{}/{}", url, path));
+            match self.acl {
+                true => request.header("X-Consul-Token", &self.token),
+                false => request,
+            }
+        };
+
+        for (key, value) in self.kv.iter() {
+            request(format!("kv/{}", encode(key)))
+                .body(value.clone())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("failed writing key: {}", e))?;
+        }
+
+        for service in self.services.iter() {
+            request(String::from("agent/service/register"))
+                .json(&service.to_json())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("failed registering service: {}", e))?;
+        }
+
+        Ok(())
+    }
 }
 
 impl Config for ConsulServerConfig {
     fn into_composition(self) -> dockertest::Composition {
-        let ports = vec![(PORT, self.port)];
+        let mut ports = vec![(PORT, self.port)];
+        if let Some(dns_port) = self.dns_port {
+            ports.push((DNS_PORT, dns_port));
+        }
+
+        let mut env = self.env.clone();
+        if self.acl {
+            let mut config = env
+                .get("CONSUL_LOCAL_CONFIG")
+                .and_then(|c| serde_json::from_str(c).ok())
+                .unwrap_or_else(|| json!({}));
+            merge(&mut config, self.acl_config());
+            env.insert(String::from("CONSUL_LOCAL_CONFIG"), config.to_string());
+        }
 
         let timeout = self.timeout;
-        let wait = Box::new(waitfor::MessageWait {
+        let inner = Box::new(waitfor::MessageWait {
             message: LOG_MSG.into(),
             source: waitfor::MessageSource::Stdout,
             timeout,
         });
+        let wait: Box<dyn waitfor::WaitFor> = match self.kv.is_empty() && self.services.is_empty() {
+            true => inner,
+            false => {
+                let config = self.clone();
+                Box::new(HookWait::new(inner, move |_| config.clone().seed()))
+            }
+        };
 
         ContainerConfig {
             args: self.args,
-            env: self.env,
+            env,
             handle: self.handle,
             name: IMAGE.into(),
             source: SOURCE,
@@ -73,15 +242,45 @@ impl Config for ConsulServerConfig {
     }
 }
 
+/// Recursively merges the given JSON value into another, overwriting any
+/// values which aren't objects in both.
+fn merge(target: &mut Value, source: Value) {
+    match (target, source) {
+        (Value::Object(target), Value::Object(source)) => {
+            for (key, value) in source {
+                merge(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (target, source) => *target = source,
+    }
+}
+
+/// Percent-encodes a KV key for use in a URL path, keeping the `/` separators.
+fn encode(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
 /// A running instance of a Consul server.
 ///
-/// The server URL which is accessible from the local host can be found in
-/// `local_address`. Other running containers which need access to this server
-/// should use the `address` field instead.
+/// When ACLs are enabled the `token` field contains the initial management
+/// token which can be used for authenticating requests. The server URL which
+/// is accessible from the local host can be found with `external_url`. Other
+/// running containers which need access to this server should use
+/// `internal_url` instead.
 pub struct ConsulServer {
+    pub external_dns_port: Option<u32>,
     pub external_port: u32,
+    pub internal_dns_port: u32,
     pub internal_port: u32,
     pub ip: String,
+    pub token: Option<String>,
 }
 
 impl ConsulServer {
@@ -98,6 +297,13 @@ impl ConsulServer {
         self.format_address("localhost", self.external_port)
     }
 
+    /// The external DNS address in the form of localhost:{port}, if the DNS
+    /// port was exposed
+    pub fn external_dns_address(&self) -> Option<String> {
+        self.external_dns_port
+            .map(|port| self.format_address("localhost", port))
+    }
+
     /// The external HTTP address
     pub fn external_url(&self) -> String {
         self.format_url("localhost", self.external_port)
@@ -108,6 +314,11 @@ impl ConsulServer {
         self.format_address(self.ip.as_str(), self.internal_port)
     }
 
+    /// The container internal DNS address in the form of {ip}:{port}
+    pub fn internal_dns_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_dns_port)
+    }
+
     /// The internal HTTP address
     pub fn internal_url(&self) -> String {
         self.format_url(self.ip.as_str(), self.internal_port)
@@ -119,18 +330,291 @@ impl Server for ConsulServer {
 
     fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
         ConsulServer {
+            external_dns_port: config.dns_port,
             external_port: config.port,
+            internal_dns_port: DNS_PORT,
             internal_port: PORT,
             ip: container.ip().to_string(),
+            token: config.acl.then(|| config.token.clone()),
         }
     }
 }
 
+/// Configuration for creating a cluster of Hashicorp Consul agents.
+///
+/// The cluster consists of `servers` server agents, which expect each other
+/// in order to bootstrap the cluster, and `clients` client agents. Every agent
+/// joins the first server agent, which uses the `handle` field as its handle,
+/// while every other agent has its role and index appended to it. The cluster
+/// is only considered ready once every agent is aware of the elected leader.
+///
+/// Each agent listens on port 8500 for HTTP requests, port 8600 for DNS
+/// requests and port 8502 for gRPC requests. These are exposed on the
+/// containers starting at the ports given by the `port`, `dns_port` and
+/// `grpc_port` fields respectively, increasing by one for each agent (servers
+/// first). Note that only TCP ports are exposed, so DNS queries from the local
+/// host must be made over TCP.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct ConsulClusterConfig {
+    #[builder(default = "0")]
+    pub clients: u8,
+    #[builder(default = "9600")]
+    pub dns_port: u32,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "9700")]
+    pub grpc_port: u32,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "9500")]
+    pub port: u32,
+    #[builder(default = "3")]
+    pub servers: u8,
+    #[builder(default = "60")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"latest\")")]
+    pub version: String,
+    #[builder(setter(skip))]
+    ips: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl ConsulClusterConfig {
+    pub fn builder() -> ConsulClusterConfigBuilder {
+        ConsulClusterConfigBuilder::default()
+    }
+
+    /// The handle of the agent at the given index
+    fn agent_handle(&self, index: u8) -> String {
+        match index {
+            0 => self.handle.clone(),
+            i if i < self.servers() => format!("{}-server-{}", self.handle, i),
+            i => format!("{}-client-{}", self.handle, i - self.servers()),
+        }
+    }
+
+    /// The total number of agents in the cluster
+    fn agents(&self) -> u8 {
+        self.servers() + self.clients
+    }
+
+    fn servers(&self) -> u8 {
+        self.servers.max(1)
+    }
+
+    /// Returns the arguments for the agent at the given index.
+    fn agent_args(&self, index: u8) -> Vec<String> {
+        let mut args = vec![String::from("agent")];
+        if index < self.servers() {
+            args.push(String::from("-server"));
+            args.push(format!("-bootstrap-expect={}", self.servers()));
+        }
+        args.extend([
+            format!("-node={}", self.agent_handle(index)),
+            String::from("-client=0.0.0.0"),
+            format!("-retry-join={}", self.agent_handle(0)),
+            format!("-hcl=ports {{ grpc = {} }}", GRPC_PORT),
+        ]);
+        args
+    }
+}
+
+impl Config for ConsulClusterConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        self.into_compositions().remove(0)
+    }
+
+    fn into_compositions(self) -> Vec<dockertest::Composition> {
+        (0..self.agents())
+            .map(|index| {
+                let handle = self.agent_handle(index);
+                let offset = u32::from(index);
+                let ports = vec![
+                    (PORT, self.port + offset),
+                    (DNS_PORT, self.dns_port + offset),
+                    (GRPC_PORT, self.grpc_port + offset),
+                ];
+
+                let url = format!("http://localhost:{}/v1/status/leader", self.port + offset);
+                let (ips, timeout) = (self.ips.clone(), self.timeout);
+                let hook_handle = handle.clone();
+                let wait = Box::new(HookWait::running(timeout, move |container| {
+                    let (url, ips, handle) = (url.clone(), ips.clone(), hook_handle.clone());
+                    async move {
+                        let client = reqwest::Client::new();
+                        retry(timeout, || async {
+                            let leader = client
+                                .get(&url)
+                                .send()
+                                .await
+                                .and_then(|r| r.error_for_status())
+                                .map_err(|e| e.to_string())?
+                                .json::<String>()
+                                .await
+                                .map_err(|e| e.to_string())?;
+                            match leader.is_empty() {
+                                true => Err(String::from("no cluster leader")),
+                                false => Ok(()),
+                            }
+                        })
+                        .await?;
+
+                        ips.lock()
+                            .unwrap()
+                            .insert(handle, container.ip().to_string());
+                        Ok(())
+                    }
+                }));
+
+                let mut composition: dockertest::Composition = ContainerConfig {
+                    args: self.agent_args(index),
+                    env: self.env.clone(),
+                    handle: handle.clone(),
+                    name: IMAGE.into(),
+                    source: SOURCE,
+                    version: self.version.clone(),
+                    ports: Some(ports),
+                    wait: Some(wait),
+                    bind_mounts: HashMap::new(),
+                }
+                .into();
+
+                // Agents join the cluster using the handle of the first server
+                composition.alias(handle);
+                composition
+            })
+            .collect()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// A single agent in a [ConsulCluster].
+///
+/// The agent URL which is accessible from the local host can be found with
+/// `external_url`, while other running containers should use `internal_url`.
+/// The DNS and gRPC ports are available in the same way through the
+/// `*_dns_address` and `*_grpc_address` methods.
+pub struct ConsulAgent {
+    pub external_dns_port: u32,
+    pub external_grpc_port: u32,
+    pub external_port: u32,
+    pub handle: String,
+    pub internal_dns_port: u32,
+    pub internal_grpc_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+    pub server: bool,
+}
+
+impl ConsulAgent {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost::{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external DNS address in the form of localhost:{port}
+    pub fn external_dns_address(&self) -> String {
+        self.format_address("localhost", self.external_dns_port)
+    }
+
+    /// The external gRPC address in the form of localhost:{port}
+    pub fn external_grpc_address(&self) -> String {
+        self.format_address("localhost", self.external_grpc_port)
+    }
+
+    /// The external HTTP address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The container internal DNS address in the form of {ip}:{port}
+    pub fn internal_dns_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_dns_port)
+    }
+
+    /// The container internal gRPC address in the form of {ip}:{port}
+    pub fn internal_grpc_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_grpc_port)
+    }
+
+    /// The internal HTTP address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+/// A running cluster of Consul agents.
+///
+/// The `agents` field contains every server agent followed by every client
+/// agent.
+pub struct ConsulCluster {
+    pub agents: Vec<ConsulAgent>,
+}
+
+impl ConsulCluster {
+    /// The client agents of the cluster
+    pub fn clients(&self) -> impl Iterator<Item = &ConsulAgent> {
+        self.agents.iter().filter(|a| !a.server)
+    }
+
+    /// The server agents of the cluster
+    pub fn servers(&self) -> impl Iterator<Item = &ConsulAgent> {
+        self.agents.iter().filter(|a| a.server)
+    }
+}
+
+impl Server for ConsulCluster {
+    type Config = ConsulClusterConfig;
+
+    fn new(config: &Self::Config, _: &dockertest::RunningContainer) -> Self {
+        let ips = config.ips.lock().unwrap();
+        let agents = (0..config.agents())
+            .map(|index| {
+                let handle = config.agent_handle(index);
+                let offset = u32::from(index);
+                ConsulAgent {
+                    external_dns_port: config.dns_port + offset,
+                    external_grpc_port: config.grpc_port + offset,
+                    external_port: config.port + offset,
+                    internal_dns_port: DNS_PORT,
+                    internal_grpc_port: GRPC_PORT,
+                    internal_port: PORT,
+                    ip: ips.get(&handle).cloned().unwrap_or_default(),
+                    server: index < config.servers(),
+                    handle,
+                }
+            })
+            .collect();
+
+        ConsulCluster { agents }
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use super::{ConsulServer, ConsulServerConfig};
+    use super::{
+        ConsulCheck, ConsulCluster, ConsulClusterConfig, ConsulServer, ConsulServerConfig,
+        ConsulService,
+    };
     use crate::Test;
+    use std::collections::HashMap;
 
     const PORT: u32 = 9500;
 
@@ -153,4 +637,166 @@ mod tests {
             assert_eq!(resp.unwrap().status(), 200);
         });
     }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(super::encode("app/config"), "app/config");
+        assert_eq!(super::encode("app/a b?c#d"), "app/a%20b%3Fc%23d");
+    }
+
+    #[test]
+    fn test_acl_config() {
+        let config = ConsulServerConfig::builder()
+            .acl(true)
+            .env(HashMap::from([(
+                "CONSUL_LOCAL_CONFIG".into(),
+                r#"{"acl": {"tokens": {"default": "anonymous"}}, "log_level": "debug"}"#.into(),
+            )]))
+            .token("token".into())
+            .version("1.9.9".into())
+            .build()
+            .unwrap();
+        assert_eq!(config.acl_config()["acl"]["tokens"]["master"], "token");
+
+        let mut local = serde_json::from_str(&config.env["CONSUL_LOCAL_CONFIG"]).unwrap();
+        super::merge(&mut local, config.acl_config());
+        assert_eq!(local["log_level"], "debug");
+        assert_eq!(local["acl"]["tokens"]["default"], "anonymous");
+        assert_eq!(local["acl"]["tokens"]["master"], "token");
+
+        for version in ["1.15.4", "latest"] {
+            let config = ConsulServerConfig::builder()
+                .version(version.into())
+                .build()
+                .unwrap();
+            let tokens = &config.acl_config()["acl"]["tokens"];
+            assert!(tokens["master"].is_null());
+            assert_eq!(tokens["initial_management"], config.token);
+        }
+    }
+
+    #[test]
+    fn test_consul_seed() {
+        let service = ConsulService::builder()
+            .name("web")
+            .address("10.0.0.1")
+            .port(8080)
+            .tags(vec!["primary".into()])
+            .check(ConsulCheck::Ttl("30s".into()))
+            .build()
+            .unwrap();
+        let config = ConsulServerConfig::builder()
+            .dns_port(PORT + 102)
+            .kv(HashMap::from([
+                ("app/config".into(), "value".into()),
+                ("app/query?#".into(), "encoded".into()),
+            ]))
+            .port(PORT + 2)
+            .services(vec![service])
+            .version("1.15.4".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: ConsulServer = instance.server();
+
+            let client = reqwest::Client::new();
+            let value = client
+                .get(format!("{}/v1/kv/app/config?raw", server.external_url()))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            assert_eq!(value, "value");
+
+            let value = client
+                .get(format!(
+                    "{}/v1/kv/{}?raw",
+                    server.external_url(),
+                    super::encode("app/query?#")
+                ))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+            assert_eq!(value, "encoded");
+            assert!(server.external_dns_address().is_some());
+
+            let services = client
+                .get(format!("{}/v1/catalog/service/web", server.external_url()))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+            assert_eq!(services[0]["ServicePort"], 8080);
+            assert_eq!(services[0]["ServiceTags"][0], "primary");
+        });
+    }
+
+    #[test]
+    fn test_consul_acl() {
+        let config = ConsulServerConfig::builder()
+            .acl(true)
+            .port(PORT + 1)
+            .version("1.15.4".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: ConsulServer = instance.server();
+
+            let client = reqwest::Client::new();
+            let url = format!("{}/v1/acl/token/self", server.external_url());
+            let resp = client.get(&url).send().await;
+            assert_eq!(resp.unwrap().status(), 403);
+
+            let resp = client
+                .get(&url)
+                .header("X-Consul-Token", server.token.unwrap())
+                .send()
+                .await;
+            assert_eq!(resp.unwrap().status(), 200);
+        });
+    }
+
+    #[test]
+    fn test_consul_cluster() {
+        let config = ConsulClusterConfig::builder()
+            .clients(1)
+            .dns_port(PORT + 110)
+            .port(PORT + 10)
+            .version("1.15.4".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let cluster: ConsulCluster = instance.server();
+            assert_eq!(cluster.servers().count(), 3);
+            assert_eq!(cluster.clients().count(), 1);
+
+            let client = reqwest::Client::new();
+            let agent = cluster.clients().next().unwrap();
+            let peers = client
+                .get(format!("{}/v1/status/peers", agent.external_url()))
+                .send()
+                .await
+                .unwrap()
+                .json::<Vec<String>>()
+                .await
+                .unwrap();
+            assert_eq!(peers.len(), 3);
+        });
+    }
 }
@@ -1,12 +1,22 @@
-use crate::common::rand_string;
+use super::consul::ConsulServerConfig;
+use crate::common::write_tempfile;
+use crate::waitfor::{retry, HookWait};
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
 use dockertest::{waitfor, Source};
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::{TempDir, TempPath};
 
+const CONSUL_IMAGE: &str = "consul";
+const DASHBOARD_IMAGE: &str = "hashicorp/dashboard-service";
+const DASHBOARD_PORT: u32 = 9002;
+const ENVOY_IMAGE: &str = "envoyproxy/envoy";
 const IMAGE: &str = "hashicorp/counting-service";
 const PORT: u32 = 9001;
 const LOG_MSG: &str = "Serving at";
+const SIDECAR_PORT: u32 = 21000;
 const SOURCE: Source = Source::DockerHub;
 
 /// Configuration for creating a Hashicorp Counting Server instance
@@ -15,6 +25,23 @@ const SOURCE: Source = Source::DockerHub;
 /// is exposed on the container by default, but the exposed port can be
 /// controlled by setting the `port` field.
 ///
+/// Setting the `dashboard` field brings up the dashboard service alongside the
+/// counting service, configured to display its count. The dashboard listens
+/// on port 9002 for HTTP requests, which is exposed on the container using the
+/// `dashboard_port` field.
+///
+/// Setting the `consul` field to the configuration of a Consul server which is
+/// registered with the same test brings the services up as a Consul Connect
+/// service mesh. The services are registered as `counting` and `dashboard`
+/// and each is given an Envoy sidecar proxy, using the `envoy_version` tag of
+/// the Envoy image, which is bootstrapped by the Consul server over gRPC. The
+/// dashboard then reaches the counting service through the upstream listener
+/// of its sidecar on port 9001 instead of directly. The services are only
+/// considered ready once Consul reports their sidecars as healthy. Note that
+/// the Consul server must have gRPC enabled on port 8502, which is the default
+/// in development mode, and the Envoy version must be supported by the version
+/// of Consul being used.
+///
 /// See the [Dockerhub](https://hub.docker.com/r/hashicorp/counting-service)
 /// page for more information on the arguments and environment variables that
 /// can be used to configure the server.
@@ -23,49 +50,417 @@ const SOURCE: Source = Source::DockerHub;
 pub struct CountingServerConfig {
     #[builder(default = "Vec::new()")]
     pub args: Vec<String>,
+    #[builder(default, setter(strip_option))]
+    pub consul: Option<ConsulServerConfig>,
+    #[builder(default = "false")]
+    pub dashboard: bool,
+    #[builder(default = "9502")]
+    pub dashboard_port: u32,
+    #[builder(default = "String::from(\"0.0.4\")")]
+    pub dashboard_version: String,
     #[builder(default = "HashMap::new()")]
     pub env: HashMap<String, String>,
+    #[builder(default = "String::from(\"v1.25.4\")")]
+    pub envoy_version: String,
     #[builder(default = "crate::server::new_handle(IMAGE)")]
     pub handle: String,
     #[builder(default = "9500")]
     pub port: u32,
     #[builder(default = "15")]
     pub timeout: u16,
-    #[builder(default = "rand_string(16)")]
-    pub token: String,
     #[builder(default = "String::from(\"0.0.2\")")]
     pub version: String,
+    #[builder(setter(skip))]
+    state: Arc<Mutex<CountingState>>,
+}
+
+/// Data generated while a [CountingServerConfig] is brought up.
+#[derive(Default)]
+struct CountingState {
+    dir: Option<TempDir>,
+    files: Vec<TempPath>,
+    ips: HashMap<String, String>,
 }
 
 impl CountingServerConfig {
     pub fn builder() -> CountingServerConfigBuilder {
         CountingServerConfigBuilder::default()
     }
+
+    /// The handle of the container which provides the Consul binary
+    fn consul_handle(&self) -> String {
+        format!("{}-consul", self.handle)
+    }
+
+    /// The handle of the dashboard
+    fn dashboard_handle(&self) -> String {
+        format!("{}-dashboard", self.handle)
+    }
+
+    /// The handle of the sidecar proxy of the given service
+    fn sidecar_handle(&self, service: &str) -> String {
+        format!("{}-{}-sidecar", self.handle, service)
+    }
+
+    /// Records the IP address of the container with the given handle.
+    fn record_ip(&self, handle: String, ip: String) {
+        self.state.lock().unwrap().ips.insert(handle, ip);
+    }
+
+    /// Returns the IP address recorded for the container with the given handle.
+    fn ip(&self, handle: &str) -> String {
+        self.state
+            .lock()
+            .unwrap()
+            .ips
+            .get(handle)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Waits for the dashboard to respond to requests and registers it with
+    /// Consul when the services form a service mesh.
+    async fn dashboard_ready(self, ip: String) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{}", self.dashboard_port);
+        retry(self.timeout, || async {
+            client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+        self.record_ip(self.dashboard_handle(), ip.clone());
+
+        if let Some(consul) = &self.consul {
+            let upstreams = json!([{
+                "DestinationName": "counting",
+                "LocalBindAddress": "0.0.0.0",
+                "LocalBindPort": PORT,
+            }]);
+            let sidecar_ip = self.ip(&self.sidecar_handle("dashboard"));
+            let service = mesh_service("dashboard", &ip, DASHBOARD_PORT, &sidecar_ip, upstreams);
+            self.consul_put(consul, "agent/service/register", service)
+                .await?;
+            self.consul_put(
+                consul,
+                "connect/intentions/exact?source=dashboard&destination=counting",
+                json!({"Action": "allow"}),
+            )
+            .await?;
+            self.wait_for_sidecar(consul, "dashboard").await?;
+        }
+        Ok(())
+    }
+
+    /// Registers the counting service with Consul along with the sidecar at
+    /// the given address and waits for the sidecar to become healthy.
+    async fn counting_sidecar_ready(self, sidecar_ip: String) -> Result<(), String> {
+        let consul = match &self.consul {
+            Some(consul) => consul,
+            None => return Ok(()),
+        };
+
+        let ip = self.ip(&self.handle);
+        let service = mesh_service("counting", &ip, PORT, &sidecar_ip, json!([]));
+        self.consul_put(consul, "agent/service/register", service)
+            .await?;
+        self.wait_for_sidecar(consul, "counting").await
+    }
+
+    /// Sends the given body to the given path of the Consul API, retrying
+    /// until the server accepts it.
+    async fn consul_put(
+        &self,
+        consul: &ConsulServerConfig,
+        path: &str,
+        body: Value,
+    ) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{}/v1/{}", consul.port, path);
+        retry(self.timeout, || async {
+            let mut request = client.put(&url).json(&body);
+            if consul.acl {
+                request = request.header("X-Consul-Token", &consul.token);
+            }
+            request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map(|_| ())
+                .map_err(|e| format!("failed calling {}: {}", path, e))
+        })
+        .await
+    }
+
+    /// Waits for Consul to report the sidecar of the given service as healthy.
+    async fn wait_for_sidecar(
+        &self,
+        consul: &ConsulServerConfig,
+        service: &str,
+    ) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!(
+            "http://localhost:{}/v1/health/connect/{}?passing=true",
+            consul.port, service
+        );
+        retry(self.timeout, || async {
+            let mut request = client.get(&url);
+            if consul.acl {
+                request = request.header("X-Consul-Token", &consul.token);
+            }
+            let instances: Vec<Value> = request
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            match instances.is_empty() {
+                true => Err(format!("sidecar of {} is not healthy", service)),
+                false => Ok(()),
+            }
+        })
+        .await
+    }
+
+    /// Writes the sidecar script and creates the directory which the Consul
+    /// binary is copied into, returning both of their paths.
+    fn sidecar_files(&self) -> Result<(String, String), String> {
+        let script = write_tempfile(
+            "sidecar",
+            ".sh",
+            include_str!("./sidecar.sh").as_bytes(),
+            0o755,
+        )
+        .map_err(|e| format!("failed writing sidecar script: {}", e))?;
+        let dir =
+            tempfile::tempdir().map_err(|e| format!("failed creating sidecar directory: {}", e))?;
+
+        let paths = (
+            script.to_string_lossy().to_string(),
+            dir.path().to_string_lossy().to_string(),
+        );
+        let mut state = self.state.lock().unwrap();
+        state.files.push(script);
+        state.dir = Some(dir);
+        Ok(paths)
+    }
+
+    /// Returns the compositions which provide the sidecar proxies of the
+    /// given services, preceded by the one which provides the Consul binary.
+    fn sidecar_compositions(
+        &self,
+        consul: &ConsulServerConfig,
+        services: &[&str],
+    ) -> Vec<dockertest::Composition> {
+        let (files, failed) = match self.sidecar_files() {
+            Ok(files) => (files, None),
+            Err(e) => ((String::new(), String::new()), Some(e)),
+        };
+        let (script, dir) = files;
+
+        // Failing to write the files fails the test
+        let wait = |hook: HookWait| match &failed {
+            Some(e) => Box::new(HookWait::fail(e.clone())),
+            None => Box::new(hook),
+        };
+        let mounts = |mounts: Vec<(&str, &str)>| match &failed {
+            Some(_) => HashMap::new(),
+            None => mounts
+                .into_iter()
+                .map(|(remote, local)| (remote.to_string(), local.to_string()))
+                .collect(),
+        };
+
+        // The Consul binary is copied from its image into the sidecars, which
+        // use it to bootstrap Envoy
+        let inner = Box::new(waitfor::ExitedWait {
+            check_interval: 1,
+            max_checks: self.timeout.into(),
+        });
+        let mut compositions: Vec<dockertest::Composition> = vec![ContainerConfig {
+            args: vec![
+                String::from("sh"),
+                String::from("-c"),
+                String::from(
+                    "cp /bin/consul /consul/bin/consul.tmp && mv /consul/bin/consul.tmp /consul/bin/consul",
+                ),
+            ],
+            env: HashMap::new(),
+            handle: self.consul_handle(),
+            name: CONSUL_IMAGE.into(),
+            source: SOURCE,
+            version: consul.version.clone(),
+            ports: None,
+            wait: Some(wait(HookWait::new(inner, |_| async { Ok(()) }))),
+            bind_mounts: mounts(vec![("/consul/bin", &dir)]),
+        }
+        .into()];
+
+        for service in services {
+            let mut env = HashMap::from([
+                (String::from("ENVOY_UID"), String::from("0")),
+                (String::from("SERVICE"), service.to_string()),
+            ]);
+            if consul.acl {
+                env.insert(String::from("CONSUL_HTTP_TOKEN"), consul.token.clone());
+            }
+
+            let config = self.clone();
+            let handle = self.sidecar_handle(service);
+            let hook = match *service {
+                "counting" => HookWait::running(self.timeout, move |container| {
+                    config
+                        .clone()
+                        .counting_sidecar_ready(container.ip().to_string())
+                }),
+                _ => {
+                    let handle = handle.clone();
+                    HookWait::running(self.timeout, move |container| {
+                        config.record_ip(handle.clone(), container.ip().to_string());
+                        async { Ok(()) }
+                    })
+                }
+            };
+
+            let mut composition: dockertest::Composition = ContainerConfig {
+                args: vec![String::from("sh"), String::from("/sidecar.sh")],
+                env,
+                handle: handle.clone(),
+                name: ENVOY_IMAGE.into(),
+                source: SOURCE,
+                version: self.envoy_version.clone(),
+                ports: None,
+                wait: Some(wait(hook)),
+                bind_mounts: mounts(vec![("/sidecar.sh", &script), ("/consul/bin", &dir)]),
+            }
+            .into();
+
+            // Other services reach their upstreams through the sidecar's handle
+            composition.alias(handle);
+            composition.inject_container_name(consul.handle.clone(), "CONSUL_HOST");
+            compositions.push(composition);
+        }
+
+        compositions
+    }
+}
+
+/// Returns the registration of a Connect enabled service along with its
+/// sidecar proxy, which runs in a separate container.
+fn mesh_service(name: &str, ip: &str, port: u32, sidecar_ip: &str, upstreams: Value) -> Value {
+    json!({
+        "Name": name,
+        "Address": ip,
+        "Port": port,
+        "Connect": {
+            "SidecarService": {
+                "Address": sidecar_ip,
+                "Port": SIDECAR_PORT,
+                "Proxy": {
+                    "LocalServiceAddress": ip,
+                    "LocalServicePort": port,
+                    "Upstreams": upstreams,
+                },
+            },
+        },
+    })
 }
 
 impl Config for CountingServerConfig {
     fn into_composition(self) -> dockertest::Composition {
+        self.into_compositions().remove(0)
+    }
+
+    fn into_compositions(self) -> Vec<dockertest::Composition> {
         let ports = vec![(PORT, self.port)];
 
         let timeout = self.timeout;
-        let wait = Box::new(waitfor::MessageWait {
+        let inner = Box::new(waitfor::MessageWait {
             message: LOG_MSG.into(),
             source: waitfor::MessageSource::Stdout,
             timeout,
         });
+        let config = self.clone();
+        let wait = Box::new(HookWait::new(inner, move |container| {
+            config.record_ip(config.handle.clone(), container.ip().to_string());
+            async { Ok(()) }
+        }));
 
-        ContainerConfig {
-            args: self.args,
-            env: self.env,
-            handle: self.handle,
+        let mut compositions: Vec<dockertest::Composition> = vec![ContainerConfig {
+            args: self.args.clone(),
+            env: self.env.clone(),
+            handle: self.handle.clone(),
             name: IMAGE.into(),
             source: SOURCE,
-            version: self.version,
+            version: self.version.clone(),
             ports: Some(ports),
             wait: Some(wait),
             bind_mounts: HashMap::new(),
         }
-        .into()
+        .into()];
+
+        if let Some(consul) = &self.consul {
+            let services: &[&str] = match self.dashboard {
+                true => &["counting", "dashboard"],
+                false => &["counting"],
+            };
+            compositions.extend(self.sidecar_compositions(consul, services));
+        }
+
+        if self.dashboard {
+            // The dashboard connects to the counting service using its handle,
+            // or to the upstream listener of its sidecar in a service mesh
+            let counting_host = match self.consul {
+                Some(_) => self.sidecar_handle("dashboard"),
+                None => {
+                    compositions[0].alias(self.handle.clone());
+                    self.handle.clone()
+                }
+            };
+            let env = HashMap::from([
+                (String::from("PORT"), DASHBOARD_PORT.to_string()),
+                (
+                    String::from("COUNTING_SERVICE_URL"),
+                    format!("http://{}:{}", counting_host, PORT),
+                ),
+            ]);
+
+            let config = self.clone();
+            let wait = Box::new(HookWait::running(self.timeout, move |container| {
+                config.clone().dashboard_ready(container.ip().to_string())
+            }));
+
+            compositions.push(
+                ContainerConfig {
+                    args: Vec::new(),
+                    env,
+                    handle: self.dashboard_handle(),
+                    name: DASHBOARD_IMAGE.into(),
+                    source: SOURCE,
+                    version: self.dashboard_version.clone(),
+                    ports: Some(vec![(DASHBOARD_PORT, self.dashboard_port)]),
+                    wait: Some(wait),
+                    bind_mounts: HashMap::new(),
+                }
+                .into(),
+            );
+        }
+
+        // Each service is registered once its sidecar is running, so the
+        // containers are started in order
+        match self.consul {
+            Some(_) => compositions
+                .into_iter()
+                .map(|c| c.with_start_policy(dockertest::StartPolicy::Strict))
+                .collect(),
+            None => compositions,
+        }
     }
 
     fn handle(&self) -> &str {
@@ -73,8 +468,51 @@ impl Config for CountingServerConfig {
     }
 }
 
+/// A running instance of the dashboard service.
+pub struct CountingDashboard {
+    pub external_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+}
+
+impl CountingDashboard {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost::{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external HTTP address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal HTTP address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
 /// A running instance of a Counting server.
+///
+/// The `dashboard` field contains the dashboard service when it was enabled.
+/// When brought up as a service mesh, the services are registered with Consul
+/// as `counting` and `dashboard` and the dashboard displays the count it
+/// receives through the sidecar proxies.
 pub struct CountingServer {
+    pub dashboard: Option<CountingDashboard>,
     pub external_port: u32,
     pub internal_port: u32,
     pub ip: String,
@@ -114,7 +552,17 @@ impl Server for CountingServer {
     type Config = CountingServerConfig;
 
     fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        let dashboard = match config.dashboard {
+            true => Some(CountingDashboard {
+                external_port: config.dashboard_port,
+                internal_port: DASHBOARD_PORT,
+                ip: config.ip(&config.dashboard_handle()),
+            }),
+            false => None,
+        };
+
         CountingServer {
+            dashboard,
             external_port: config.port,
             internal_port: PORT,
             ip: container.ip().to_string(),
@@ -126,10 +574,78 @@ impl Server for CountingServer {
 mod tests {
 
     use super::{CountingServer, CountingServerConfig};
+    use crate::servers::hashi::{ConsulServer, ConsulServerConfig};
+    use crate::waitfor::retry;
     use crate::Test;
+    use serde_json::Value;
+    use std::collections::HashMap;
 
     const PORT: u32 = 9001;
 
+    /// Splits a polling payload of the Engine.IO protocol into its packets.
+    fn packets(mut payload: &str) -> Vec<&str> {
+        let mut packets = Vec::new();
+        while let Some((length, rest)) = payload.split_once(':') {
+            match length.parse::<usize>().ok().and_then(|l| rest.get(..l)) {
+                Some(packet) => {
+                    packets.push(packet);
+                    payload = &rest[packet.len()..];
+                }
+                None => break,
+            }
+        }
+        packets
+    }
+
+    /// Returns the first count which the dashboard at the given URL sends to
+    /// its clients over Socket.IO.
+    async fn dashboard_count(url: &str) -> Result<i64, String> {
+        let client = reqwest::Client::new();
+        let url = format!("{}/socket.io/?EIO=3&transport=polling&b64=1", url);
+        let poll = |url: String| {
+            let client = client.clone();
+            async move {
+                client
+                    .get(url)
+                    .send()
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| e.to_string())?
+                    .text()
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        };
+
+        let handshake = poll(url.clone()).await?;
+        let sid = packets(&handshake)
+            .iter()
+            .filter_map(|p| p.strip_prefix('0'))
+            .filter_map(|p| serde_json::from_str::<Value>(p).ok())
+            .find_map(|p| p["sid"].as_str().map(String::from))
+            .ok_or_else(|| format!("missing session: {}", handshake))?;
+
+        for _ in 0..10 {
+            let payload = poll(format!("{}&sid={}", url, sid)).await?;
+            let count = packets(&payload)
+                .iter()
+                .filter_map(|p| p.strip_prefix("42"))
+                .filter_map(|p| serde_json::from_str::<Value>(p).ok())
+                .find_map(|event| event[1]["count"].as_i64());
+            if let Some(count) = count {
+                return Ok(count);
+            }
+        }
+        Err(String::from("dashboard did not send a count"))
+    }
+
+    #[test]
+    fn test_packets() {
+        let payload = r#"2:4016:42["message",{}]"#;
+        assert_eq!(packets(payload), vec!["40", r#"42["message",{}]"#]);
+        assert!(packets("5:40").is_empty());
+    }
+
     #[test]
     fn test_counting() {
         let config = CountingServerConfig::builder().port(PORT).build().unwrap();
@@ -145,4 +661,61 @@ mod tests {
             assert_eq!(resp.unwrap().status(), 200);
         });
     }
+
+    #[test]
+    fn test_counting_mesh() {
+        let consul = ConsulServerConfig::builder()
+            .env(HashMap::from([(
+                "CONSUL_LOCAL_CONFIG".into(),
+                r#"{"ports": {"grpc": 8502}}"#.into(),
+            )]))
+            .port(9520)
+            .version("1.15.4".into())
+            .build()
+            .unwrap();
+        let config = CountingServerConfig::builder()
+            .consul(consul.clone())
+            .dashboard(true)
+            .dashboard_port(PORT + 2)
+            .port(PORT + 1)
+            .timeout(60)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(consul);
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: CountingServer = instance.server();
+            let consul: ConsulServer = instance.server();
+
+            let dashboard = server.dashboard.as_ref().unwrap();
+            assert_eq!(
+                dashboard.external_address(),
+                format!("localhost:{}", PORT + 2)
+            );
+
+            let client = reqwest::Client::new();
+            for service in ["counting", "dashboard"] {
+                let instances: Vec<Value> = client
+                    .get(format!(
+                        "{}/v1/health/connect/{}?passing=true",
+                        consul.external_url(),
+                        service
+                    ))
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                assert_eq!(instances.len(), 1);
+            }
+
+            // The dashboard only reaches the counting service through the mesh
+            let url = dashboard.external_url();
+            let count = retry(30, || dashboard_count(&url)).await.unwrap();
+            assert!(count > 0);
+        });
+    }
 }
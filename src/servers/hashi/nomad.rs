@@ -0,0 +1,218 @@
+use crate::waitfor::{retry, HookWait};
+use crate::{Config, ContainerConfig, Server};
+use derive_builder::Builder;
+use dockertest::{waitfor, Source};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+const IMAGE: &str = "hashicorp/nomad";
+const PORT: u32 = 4646;
+const LOG_MSG: &str = "Nomad agent started!";
+const SOURCE: Source = Source::DockerHub;
+
+/// Configuration for creating a Hashicorp Nomad server.
+///
+/// The server is started as a development agent which acts as both a server
+/// and a client. If `acl` is set the ACL system is enabled and bootstrapped
+/// before the test body runs, with the resulting management token being made
+/// available on the [NomadServer].
+///
+/// By default the Nomad server listens on port 4646 for HTTP requests. This
+/// is exposed on the container by default, but the exposed port can be
+/// controlled by setting the `port` field.
+///
+/// See the [Dockerhub](https://hub.docker.com/r/hashicorp/nomad) page for more
+/// information on the arguments and environment variables that can be used to
+/// configure the server.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct NomadServerConfig {
+    #[builder(default = "false")]
+    pub acl: bool,
+    #[builder(default = "Vec::new()")]
+    pub args: Vec<String>,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "4646")]
+    pub port: u32,
+    #[builder(default = "15")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"1.7.7\")")]
+    pub version: String,
+    #[builder(setter(skip))]
+    token: Arc<Mutex<Option<String>>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct BootstrapResponse {
+    secret_id: String,
+}
+
+impl NomadServerConfig {
+    pub fn builder() -> NomadServerConfigBuilder {
+        NomadServerConfigBuilder::default()
+    }
+
+    /// Bootstraps the ACL system and stores the resulting management token.
+    async fn bootstrap(self) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{}/v1/acl/bootstrap", self.port);
+
+        let resp = retry(self.timeout, || async {
+            client
+                .post(&url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| e.to_string())?
+                .json::<BootstrapResponse>()
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await?;
+
+        *self.token.lock().unwrap() = Some(resp.secret_id);
+        Ok(())
+    }
+}
+
+impl Config for NomadServerConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        let ports = vec![(PORT, self.port)];
+
+        let mut args = vec![
+            String::from("agent"),
+            String::from("-dev"),
+            String::from("-bind=0.0.0.0"),
+        ];
+        if self.acl {
+            args.push(String::from("-acl-enabled"));
+        }
+        args.extend(self.args.clone());
+
+        let inner = Box::new(waitfor::MessageWait {
+            message: LOG_MSG.into(),
+            source: waitfor::MessageSource::Stdout,
+            timeout: self.timeout,
+        });
+        let wait: Box<dyn waitfor::WaitFor> = match self.acl {
+            true => {
+                let config = self.clone();
+                Box::new(HookWait::new(inner, move |_| config.clone().bootstrap()))
+            }
+            false => inner,
+        };
+
+        ContainerConfig {
+            args,
+            env: self.env,
+            handle: self.handle,
+            name: IMAGE.into(),
+            source: SOURCE,
+            version: self.version,
+            ports: Some(ports),
+            wait: Some(wait),
+            bind_mounts: HashMap::new(),
+        }
+        .into()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// A running instance of a Nomad server.
+///
+/// When ACLs are enabled the `token` field contains the management token
+/// generated when the ACL system was bootstrapped. The server URL which is
+/// accessible from the local host can be found with `external_url`. Other
+/// running containers which need access to this server should use
+/// `internal_url` instead.
+pub struct NomadServer {
+    pub external_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+    pub token: Option<String>,
+}
+
+impl NomadServer {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost::{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external HTTP address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal HTTP address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+impl Server for NomadServer {
+    type Config = NomadServerConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        NomadServer {
+            external_port: config.port,
+            internal_port: PORT,
+            ip: container.ip().to_string(),
+            token: config.token.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::{NomadServer, NomadServerConfig};
+    use crate::Test;
+
+    const PORT: u32 = 9646;
+
+    #[test]
+    fn test_nomad() {
+        let config = NomadServerConfig::builder()
+            .port(PORT)
+            .acl(true)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: NomadServer = instance.server();
+            assert!(server.token.is_some());
+
+            let client = reqwest::Client::new();
+            let resp = client
+                .get(format!("{}/v1/jobs", server.external_url()))
+                .header("X-Nomad-Token", server.token.unwrap())
+                .send()
+                .await;
+            assert!(resp.is_ok());
+            assert_eq!(resp.unwrap().status(), 200);
+        });
+    }
+}
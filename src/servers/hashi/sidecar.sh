@@ -0,0 +1,23 @@
+#!/bin/sh
+# Bootstraps Envoy as the Connect sidecar proxy of a service and starts it
+set -e
+
+until [ -x /consul/bin/consul ]; do
+    sleep 1
+done
+
+# Envoy requires the address of the xDS server to be an IP address
+until CONSUL_IP=$(getent hosts "$CONSUL_HOST" | awk '{ print $1 }') && [ -n "$CONSUL_IP" ]; do
+    sleep 1
+done
+
+# The service is registered once this container is running
+until /consul/bin/consul connect envoy -bootstrap \
+    -sidecar-for "$SERVICE" \
+    -admin-bind 127.0.0.1:19000 \
+    -http-addr "$CONSUL_IP:8500" \
+    -grpc-addr "$CONSUL_IP:8502" > /tmp/envoy.json; do
+    sleep 1
+done
+
+exec envoy -c /tmp/envoy.json
@@ -1,23 +1,158 @@
-use crate::common::rand_string;
+use crate::common::{rand_string, write_tempfile};
+use crate::tls::CertificateAuthority;
+use crate::waitfor::{retry, HookWait};
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
 use dockertest::{waitfor, Source};
+use serde::Deserialize;
+use serde_json::{json, Value};
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempPath;
 
 const IMAGE: &str = "vault";
 const PORT: u32 = 8200;
+const DEV_PORT: u32 = 8210;
 const LOG_MSG: &str = "Development mode should NOT be used in production installations!";
+const LOG_MSG_SERVER: &str = "Vault server started!";
 const SOURCE: Source = Source::DockerHub;
 
+/// The storage backend used by a Vault server which isn't running in
+/// development mode.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VaultStorage {
+    File,
+    Raft,
+}
+
+/// A secrets engine to enable on a Vault server.
+///
+/// The `kind` field is the type of the engine (i.e. `kv`) and `options` are
+/// passed through as the mount options (i.e. `version` = `2`).
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct VaultSecretEngine {
+    #[builder(default = "String::new()", setter(into))]
+    pub kind: String,
+    #[builder(default = "HashMap::new()")]
+    pub options: HashMap<String, String>,
+    #[builder(default = "String::new()", setter(into))]
+    pub path: String,
+}
+
+impl VaultSecretEngine {
+    pub fn builder() -> VaultSecretEngineBuilder {
+        VaultSecretEngineBuilder::default()
+    }
+}
+
+/// An auth method to enable on a Vault server along with the roles to create
+/// on it.
+///
+/// Roles are keyed by their name and their value is passed through as the
+/// body of the request which creates them.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct VaultAuthMethod {
+    #[builder(default = "String::new()", setter(into))]
+    pub kind: String,
+    #[builder(default = "String::new()", setter(into))]
+    pub path: String,
+    #[builder(default = "HashMap::new()")]
+    pub roles: HashMap<String, Value>,
+}
+
+impl VaultAuthMethod {
+    pub fn builder() -> VaultAuthMethodBuilder {
+        VaultAuthMethodBuilder::default()
+    }
+}
+
+/// A secret to write to a Vault server.
+///
+/// The `path` is the full API path of the secret, meaning secrets written to
+/// a KV version 2 engine must include the `data/` prefix and wrap their
+/// contents in a `data` object.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct VaultSecret {
+    #[builder(default = "Value::Null")]
+    pub data: Value,
+    #[builder(default = "String::new()", setter(into))]
+    pub path: String,
+}
+
+impl VaultSecret {
+    pub fn builder() -> VaultSecretBuilder {
+        VaultSecretBuilder::default()
+    }
+}
+
+/// The resources to create on a Vault server once it's ready.
+///
+/// Secrets engines are enabled first, followed by policies (keyed by their
+/// name and containing their HCL rules), auth methods and finally secrets.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct VaultProvisioning {
+    #[builder(default = "Vec::new()")]
+    pub auth_methods: Vec<VaultAuthMethod>,
+    #[builder(default = "HashMap::new()")]
+    pub policies: HashMap<String, String>,
+    #[builder(default = "Vec::new()")]
+    pub secret_engines: Vec<VaultSecretEngine>,
+    #[builder(default = "Vec::new()")]
+    pub secrets: Vec<VaultSecret>,
+}
+
+impl VaultProvisioning {
+    pub fn builder() -> VaultProvisioningBuilder {
+        VaultProvisioningBuilder::default()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.auth_methods.is_empty()
+            && self.policies.is_empty()
+            && self.secret_engines.is_empty()
+            && self.secrets.is_empty()
+    }
+}
+
+/// The credentials of an AppRole role created while provisioning.
+#[derive(Clone, Debug)]
+pub struct VaultAppRole {
+    pub role_id: String,
+    pub secret_id: String,
+}
+
 /// Configuration for creating a Hashicorp Vault server.
 ///
-/// A token with root permissions will automatically be generated using the
-/// `token` field. If it's omitted the token will automatically be generated.
+/// By default the server runs in development mode. A token with root
+/// permissions will automatically be generated using the `token` field. If
+/// it's omitted the token will automatically be generated.
+///
+/// Setting the `storage` field instead runs the server in production mode
+/// using the given storage backend. The server is initialized with
+/// `key_shares` unseal keys, of which `key_threshold` are required to unseal
+/// it, and then automatically unsealed before the test body runs. The
+/// generated unseal keys and root token are made available on the
+/// [VaultServer] and the `token` field is ignored.
 ///
 /// By default the Vault server listens on port 8200 for HTTP requests. This
 /// is exposed on the container by default, but the exposed port can be
 /// controlled by setting the `port` field.
 ///
+/// Setting the `tls` field serves the API over HTTPS instead. A certificate
+/// authority is generated for the test and used to issue a certificate valid
+/// for `localhost`, `127.0.0.1` and the container handle. The PEM encoded
+/// authority certificate is made available on the [VaultServer] so clients
+/// can trust it.
+///
+/// Resources described by the `provisioning` field are created once the server
+/// is ready. Note that in development mode a KV version 2 engine is already
+/// mounted at `secret/`. The role and secret IDs of any roles created on an
+/// `approle` auth method are made available on the [VaultServer].
+///
 /// See the [Dockerhub](https://hub.docker.com/_/vault) page for more
 /// information on the arguments and environment variables that can be used to
 /// configure the server.
@@ -30,47 +165,391 @@ pub struct VaultServerConfig {
     pub env: HashMap<String, String>,
     #[builder(default = "crate::server::new_handle(IMAGE)")]
     pub handle: String,
+    #[builder(default = "5")]
+    pub key_shares: u8,
+    #[builder(default = "3")]
+    pub key_threshold: u8,
     #[builder(default = "8200")]
     pub port: u32,
+    #[builder(default)]
+    pub provisioning: VaultProvisioning,
+    #[builder(default)]
+    pub storage: Option<VaultStorage>,
     #[builder(default = "15")]
     pub timeout: u16,
+    #[builder(default = "false")]
+    pub tls: bool,
     #[builder(default = "rand_string(16)")]
     pub token: String,
     #[builder(default = "String::from(\"latest\")")]
     pub version: String,
+    #[builder(setter(skip))]
+    state: Arc<Mutex<VaultState>>,
+}
+
+/// Data generated while a [VaultServerConfig] is brought up.
+#[derive(Default)]
+struct VaultState {
+    approles: HashMap<String, VaultAppRole>,
+    ca: Option<CertificateAuthority>,
+    files: Vec<TempPath>,
+    root_token: Option<String>,
+    unseal_keys: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct RoleIdResponse {
+    role_id: String,
+}
+
+#[derive(Deserialize)]
+struct SecretIdResponse {
+    secret_id: String,
+}
+
+#[derive(Deserialize)]
+struct SealStatusResponse {
+    sealed: bool,
+}
+
+#[derive(Deserialize)]
+struct LeaderResponse {
+    leader_address: String,
+}
+
+#[derive(Deserialize)]
+struct InitResponse {
+    keys_base64: Vec<String>,
+    root_token: String,
 }
 
 impl VaultServerConfig {
     pub fn builder() -> VaultServerConfigBuilder {
         VaultServerConfigBuilder::default()
     }
+
+    /// Generates the server configuration used in production mode.
+    fn server_config(&self, storage: &VaultStorage) -> String {
+        let storage = match storage {
+            VaultStorage::File => String::from(r#"storage "file" { path = "/vault/file" }"#),
+            VaultStorage::Raft => format!(
+                r#"storage "raft" {{
+  path    = "/vault/file"
+  node_id = "{}"
+}}"#,
+                self.handle
+            ),
+        };
+
+        format!(
+            r#"disable_mlock = true
+api_addr      = "{scheme}://127.0.0.1:{port}"
+cluster_addr  = "https://127.0.0.1:8201"
+
+{listener}
+
+{storage}
+"#,
+            scheme = self.scheme(),
+            port = PORT,
+            listener = self.listener(),
+            storage = storage
+        )
+    }
+
+    /// Generates the configuration for the API listener.
+    fn listener(&self) -> String {
+        let tls = match self.tls {
+            true => {
+                r#"tls_cert_file = "/vault/tls/server.crt"
+  tls_key_file  = "/vault/tls/server.key""#
+            }
+            false => "tls_disable   = true",
+        };
+        format!(
+            r#"listener "tcp" {{
+  address       = "0.0.0.0:{}"
+  {}
+}}"#,
+            PORT, tls
+        )
+    }
+
+    fn scheme(&self) -> &str {
+        match self.tls {
+            true => "https",
+            false => "http",
+        }
+    }
+
+    /// Returns a client which trusts the generated certificate authority.
+    fn client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(ca) = &self.state.lock().unwrap().ca {
+            let cert = reqwest::Certificate::from_pem(ca.cert_pem().as_bytes())
+                .map_err(|e| e.to_string())?;
+            builder = builder.add_root_certificate(cert);
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+
+    /// Generates a certificate for the server and mounts it along with the
+    /// given configuration.
+    fn mount_config(
+        &self,
+        bind_mounts: &mut HashMap<String, String>,
+        config: &str,
+    ) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let mut mount = |path: &str, suffix: &str, content: &str| {
+            let file = write_tempfile("vault", suffix, content.as_bytes(), 0o644)
+                .map_err(|e| format!("failed writing vault configuration: {}", e))?;
+            bind_mounts.insert(path.to_string(), file.to_string_lossy().to_string());
+            state.files.push(file);
+            Ok::<_, String>(())
+        };
+
+        mount("/vault/config/server.hcl", ".hcl", config)?;
+        if self.tls {
+            let ca = CertificateAuthority::new("dockertest-server").map_err(|e| e.to_string())?;
+            let cert = ca
+                .issue(&self.handle, &["localhost", "127.0.0.1", &self.handle])
+                .map_err(|e| e.to_string())?;
+            mount("/vault/tls/server.crt", ".crt", &cert.cert_pem)?;
+            mount("/vault/tls/server.key", ".key", &cert.key_pem)?;
+            state.ca = Some(ca);
+        }
+        Ok(())
+    }
+
+    /// Initializes and unseals the server, storing the generated unseal keys
+    /// and root token.
+    async fn initialize(self) -> Result<(), String> {
+        let client = self.client()?;
+        let url = format!("{}://localhost:{}", self.scheme(), self.port);
+
+        let init = init(
+            &client,
+            &url,
+            self.key_shares,
+            self.key_threshold,
+            self.timeout,
+        )
+        .await?;
+        unseal(
+            &client,
+            &url,
+            &init.keys_base64,
+            self.key_threshold,
+            self.timeout,
+        )
+        .await?;
+        wait_for_health(&client, &url, false, self.timeout).await?;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            state.root_token = Some(init.root_token);
+            state.unseal_keys = init.keys_base64;
+        }
+
+        self.provision().await
+    }
+
+    /// Creates the resources described by the `provisioning` field.
+    async fn provision(self) -> Result<(), String> {
+        let client = self.client()?;
+        let url = format!("{}://localhost:{}/v1", self.scheme(), self.port);
+        let token = self
+            .state
+            .lock()
+            .unwrap()
+            .root_token
+            .clone()
+            .unwrap_or_else(|| self.token.clone());
+
+        let request = |method: reqwest::Method, path: String, body: Value| {
+            let request = client
+                .request(method, format!("{}/{}", url, path))
+                .header("X-Vault-Token", &token);
+            match body {
+                Value::Null => request.send(),
+                body => request.json(&body).send(),
+            }
+        };
+        let provisioning = &self.provisioning;
+
+        for engine in provisioning.secret_engines.iter() {
+            request(
+                reqwest::Method::POST,
+                format!("sys/mounts/{}", engine.path),
+                json!({ "type": engine.kind, "options": engine.options }),
+            )
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("failed enabling secrets engine: {}", e))?;
+        }
+
+        for (name, rules) in provisioning.policies.iter() {
+            request(
+                reqwest::Method::PUT,
+                format!("sys/policies/acl/{}", name),
+                json!({ "policy": rules }),
+            )
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("failed writing policy: {}", e))?;
+        }
+
+        let mut approles = HashMap::new();
+        for method in provisioning.auth_methods.iter() {
+            request(
+                reqwest::Method::POST,
+                format!("sys/auth/{}", method.path),
+                json!({ "type": method.kind }),
+            )
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("failed enabling auth method: {}", e))?;
+
+            for (name, role) in method.roles.iter() {
+                let path = format!("auth/{}/role/{}", method.path, name);
+                request(reqwest::Method::POST, path.clone(), role.clone())
+                    .await
+                    .and_then(|r| r.error_for_status())
+                    .map_err(|e| format!("failed creating role: {}", e))?;
+
+                if method.kind != "approle" {
+                    continue;
+                }
+
+                let role_id: DataResponse<RoleIdResponse> = request(
+                    reqwest::Method::GET,
+                    format!("{}/role-id", path),
+                    Value::Null,
+                )
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("failed reading role ID: {}", e))?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+                let secret_id: DataResponse<SecretIdResponse> = request(
+                    reqwest::Method::POST,
+                    format!("{}/secret-id", path),
+                    json!({}),
+                )
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| format!("failed generating secret ID: {}", e))?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+
+                approles.insert(
+                    name.clone(),
+                    VaultAppRole {
+                        role_id: role_id.data.role_id,
+                        secret_id: secret_id.data.secret_id,
+                    },
+                );
+            }
+        }
+
+        for secret in provisioning.secrets.iter() {
+            request(
+                reqwest::Method::POST,
+                secret.path.clone(),
+                secret.data.clone(),
+            )
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("failed writing secret: {}", e))?;
+        }
+
+        self.state.lock().unwrap().approles = approles;
+        Ok(())
+    }
 }
 
 impl Config for VaultServerConfig {
     fn into_composition(self) -> dockertest::Composition {
         let ports = vec![(PORT, self.port)];
         let mut env = self.env.clone();
-        env.insert(String::from("VAULT_DEV_ROOT_TOKEN_ID"), self.token.clone());
+        let mut args = self.args.clone();
+        let mut bind_mounts = HashMap::new();
+        let mut mounted = Ok(());
 
         let timeout = self.timeout;
-        let wait = Box::new(waitfor::MessageWait {
-            message: LOG_MSG.into(),
-            source: waitfor::MessageSource::Stdout,
-            timeout,
-        });
+        let wait: Box<dyn waitfor::WaitFor> = match &self.storage {
+            Some(storage) => {
+                mounted = self.mount_config(&mut bind_mounts, &self.server_config(storage));
+                env.insert(String::from("SKIP_SETCAP"), String::from("true"));
+                args.insert(0, String::from("server"));
+
+                let inner = Box::new(waitfor::MessageWait {
+                    message: LOG_MSG_SERVER.into(),
+                    source: waitfor::MessageSource::Stdout,
+                    timeout,
+                });
+                let config = self.clone();
+                Box::new(HookWait::new(inner, move |_| config.clone().initialize()))
+            }
+            None => {
+                // The development listener can't be configured for TLS, so it's
+                // moved aside in favor of a configured one
+                if self.tls {
+                    mounted = self.mount_config(&mut bind_mounts, &self.listener());
+                    env.insert(
+                        String::from("VAULT_DEV_LISTEN_ADDRESS"),
+                        format!("127.0.0.1:{}", DEV_PORT),
+                    );
+                }
+                env.insert(String::from("VAULT_DEV_ROOT_TOKEN_ID"), self.token.clone());
+
+                let inner = Box::new(waitfor::MessageWait {
+                    message: LOG_MSG.into(),
+                    source: waitfor::MessageSource::Stdout,
+                    timeout,
+                });
+                match self.provisioning.is_empty() {
+                    true => inner,
+                    false => {
+                        let config = self.clone();
+                        Box::new(HookWait::new(inner, move |_| config.clone().provision()))
+                    }
+                }
+            }
+        };
 
-        ContainerConfig {
-            args: self.args,
+        // Failing to generate any of the mounted files fails the test
+        let wait = match mounted {
+            Ok(()) => wait,
+            Err(e) => Box::new(HookWait::fail(e)),
+        };
+
+        let mut composition: dockertest::Composition = ContainerConfig {
+            args,
             env,
-            handle: self.handle,
+            handle: self.handle.clone(),
             name: IMAGE.into(),
             source: SOURCE,
             version: self.version,
             ports: Some(ports),
             wait: Some(wait),
-            bind_mounts: HashMap::new(),
+            bind_mounts,
+        }
+        .into();
+
+        // Allows other containers to verify the certificate by the handle
+        if self.tls {
+            composition.alias(self.handle);
         }
-        .into()
+        composition
     }
 
     fn handle(&self) -> &str {
@@ -78,17 +557,114 @@ impl Config for VaultServerConfig {
     }
 }
 
+/// Initializes the Vault server at the given URL.
+async fn init(
+    client: &reqwest::Client,
+    url: &str,
+    key_shares: u8,
+    key_threshold: u8,
+    timeout: u16,
+) -> Result<InitResponse, String> {
+    retry(timeout, || async {
+        client
+            .put(format!("{}/v1/sys/init", url))
+            .json(&json!({
+                "secret_shares": key_shares,
+                "secret_threshold": key_threshold,
+            }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json()
+            .await
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
+/// Unseals the Vault server at the given URL using the given keys.
+///
+/// Nodes joining a Raft cluster may reject keys until they've joined, so the
+/// unseal process is restarted until it succeeds.
+async fn unseal(
+    client: &reqwest::Client,
+    url: &str,
+    keys: &[String],
+    key_threshold: u8,
+    timeout: u16,
+) -> Result<(), String> {
+    let unseal = |body: Value| async move {
+        client
+            .put(format!("{}/v1/sys/unseal", url))
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("failed unsealing vault: {}", e))?
+            .json::<SealStatusResponse>()
+            .await
+            .map_err(|e| e.to_string())
+    };
+
+    retry(timeout, || async {
+        unseal(json!({ "reset": true })).await?;
+        for key in keys.iter().take(key_threshold.into()) {
+            if !unseal(json!({ "key": key })).await?.sealed {
+                return Ok(());
+            }
+        }
+        Err(String::from("vault is still sealed"))
+    })
+    .await
+}
+
+/// Waits for the Vault server at the given URL to report itself as healthy.
+///
+/// Only an active node is considered healthy unless `standby` is set.
+async fn wait_for_health(
+    client: &reqwest::Client,
+    url: &str,
+    standby: bool,
+    timeout: u16,
+) -> Result<(), String> {
+    retry(timeout, || async {
+        client
+            .get(format!("{}/v1/sys/health?standbyok={}", url, standby))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
+
 /// A running instance of a Vault server.
 ///
-/// The `token` field contains the root Vault token for the server. The server
-/// URL which is accessible from the local host can be found in `local_address`.
-/// Other running containers which need access to this server should use the
-/// `address` field instead.
+/// The `token` field contains the root Vault token for the server. When the
+/// server isn't running in development mode the `unseal_keys` field contains
+/// the unseal keys generated when the server was initialized. The server URL
+/// which is accessible from the local host can be found with `external_url`.
+/// Other running containers which need access to this server should use
+/// `internal_url` instead.
+///
+/// The `approles` field contains the credentials of any AppRole roles created
+/// while provisioning the server, keyed by the name of the role.
+///
+/// When TLS is enabled the `ca_pem` field contains the PEM encoded certificate
+/// authority which issued the server certificate. Since the certificate isn't
+/// valid for the container IP, the internal URL uses the container handle as
+/// its host instead.
 pub struct VaultServer {
+    pub approles: HashMap<String, VaultAppRole>,
+    pub ca_pem: Option<String>,
     pub external_port: u32,
+    pub handle: String,
     pub internal_port: u32,
     pub ip: String,
     pub token: String,
+    pub unseal_keys: Vec<String>,
 }
 
 impl VaultServer {
@@ -97,7 +673,12 @@ impl VaultServer {
     }
 
     fn format_url(&self, host: &str, port: u32) -> String {
-        format!("http://{}", self.format_address(host, port))
+        let scheme = if self.ca_pem.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        format!("{}://{}", scheme, self.format_address(host, port))
     }
 
     /// The external address in the form of localhost::{port}
@@ -105,7 +686,7 @@ impl VaultServer {
         self.format_address("localhost", self.external_port)
     }
 
-    /// The external HTTP address
+    /// The external HTTP(S) address
     pub fn external_url(&self) -> String {
         self.format_url("localhost", self.external_port)
     }
@@ -115,9 +696,12 @@ impl VaultServer {
         self.format_address(self.ip.as_str(), self.internal_port)
     }
 
-    /// The internal HTTP address
+    /// The internal HTTP(S) address
     pub fn internal_url(&self) -> String {
-        self.format_url(self.ip.as_str(), self.internal_port)
+        match self.ca_pem {
+            Some(_) => self.format_url(self.handle.as_str(), self.internal_port),
+            None => self.format_url(self.ip.as_str(), self.internal_port),
+        }
     }
 }
 
@@ -125,11 +709,323 @@ impl Server for VaultServer {
     type Config = VaultServerConfig;
 
     fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        let state = config.state.lock().unwrap();
         VaultServer {
+            approles: state.approles.clone(),
+            ca_pem: state.ca.as_ref().map(|ca| ca.cert_pem()),
             external_port: config.port,
+            handle: config.handle.clone(),
             internal_port: PORT,
             ip: container.ip().to_string(),
-            token: config.token.clone(),
+            token: state
+                .root_token
+                .clone()
+                .unwrap_or_else(|| config.token.clone()),
+            unseal_keys: state.unseal_keys.clone(),
+        }
+    }
+}
+
+/// Configuration for creating a cluster of Hashicorp Vault servers.
+///
+/// The cluster consists of `nodes` Vault servers using integrated Raft
+/// storage. The first node is initialized with `key_shares` unseal keys, of
+/// which `key_threshold` are required to unseal it, and every other node joins
+/// it before being unsealed with the same keys. The nodes are brought up one
+/// at a time and the cluster is only considered ready once every node has been
+/// unsealed.
+///
+/// Each node listens on port 8200 for HTTP requests. These are exposed on the
+/// containers starting at the port given by the `port` field and increasing by
+/// one for each node. The first node uses the `handle` field as its handle
+/// while every other node has its index appended to it.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct VaultClusterConfig {
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "5")]
+    pub key_shares: u8,
+    #[builder(default = "3")]
+    pub key_threshold: u8,
+    #[builder(default = "3")]
+    pub nodes: u8,
+    #[builder(default = "8200")]
+    pub port: u32,
+    #[builder(default = "30")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"latest\")")]
+    pub version: String,
+    #[builder(setter(skip))]
+    state: Arc<Mutex<VaultClusterState>>,
+}
+
+/// Data generated while a [VaultClusterConfig] is brought up.
+#[derive(Default)]
+struct VaultClusterState {
+    files: Vec<TempPath>,
+    ips: HashMap<String, String>,
+    root_token: Option<String>,
+    unseal_keys: Vec<String>,
+}
+
+impl VaultClusterConfig {
+    pub fn builder() -> VaultClusterConfigBuilder {
+        VaultClusterConfigBuilder::default()
+    }
+
+    /// The handle of the node at the given index
+    fn node_handle(&self, index: u8) -> String {
+        match index {
+            0 => self.handle.clone(),
+            i => format!("{}-{}", self.handle, i),
+        }
+    }
+
+    /// Generates the server configuration for the node at the given index.
+    fn server_config(&self, index: u8) -> String {
+        let handle = self.node_handle(index);
+        let join = match index {
+            0 => String::new(),
+            _ => format!(
+                r#"
+  retry_join {{
+    leader_api_addr = "http://{}:{}"
+  }}"#,
+                self.node_handle(0),
+                PORT
+            ),
+        };
+
+        format!(
+            r#"disable_mlock = true
+api_addr      = "http://{handle}:{port}"
+cluster_addr  = "https://{handle}:8201"
+
+listener "tcp" {{
+  address     = "0.0.0.0:{port}"
+  tls_disable = true
+}}
+
+storage "raft" {{
+  path    = "/vault/file"
+  node_id = "{handle}"{join}
+}}
+"#,
+            handle = handle,
+            port = PORT,
+            join = join
+        )
+    }
+
+    /// Brings up the node at the given index, initializing the cluster if it's
+    /// the first node.
+    async fn start_node(self, index: u8, ip: String) -> Result<(), String> {
+        let client = reqwest::Client::new();
+        let url = format!("http://localhost:{}", self.port + u32::from(index));
+
+        if index == 0 {
+            let init = init(
+                &client,
+                &url,
+                self.key_shares,
+                self.key_threshold,
+                self.timeout,
+            )
+            .await?;
+            let mut state = self.state.lock().unwrap();
+            state.root_token = Some(init.root_token);
+            state.unseal_keys = init.keys_base64;
+        }
+
+        let keys = self.state.lock().unwrap().unseal_keys.clone();
+        unseal(&client, &url, &keys, self.key_threshold, self.timeout).await?;
+        wait_for_health(&client, &url, index > 0, self.timeout).await?;
+
+        self.state
+            .lock()
+            .unwrap()
+            .ips
+            .insert(self.node_handle(index), ip);
+        Ok(())
+    }
+}
+
+impl Config for VaultClusterConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        self.into_compositions().remove(0)
+    }
+
+    fn into_compositions(self) -> Vec<dockertest::Composition> {
+        let mut env = self.env.clone();
+        env.insert(String::from("SKIP_SETCAP"), String::from("true"));
+
+        (0..self.nodes.max(1))
+            .map(|index| {
+                let handle = self.node_handle(index);
+
+                let file =
+                    write_tempfile("vault", ".hcl", self.server_config(index).as_bytes(), 0o644)
+                        .map_err(|e| format!("failed writing vault configuration: {}", e));
+                let inner = Box::new(waitfor::MessageWait {
+                    message: LOG_MSG_SERVER.into(),
+                    source: waitfor::MessageSource::Stdout,
+                    timeout: self.timeout,
+                });
+                let config = self.clone();
+                let (bind_mounts, wait) = match file {
+                    Ok(file) => {
+                        let bind_mounts = HashMap::from([(
+                            String::from("/vault/config/server.hcl"),
+                            file.to_string_lossy().to_string(),
+                        )]);
+                        self.state.lock().unwrap().files.push(file);
+                        let wait = HookWait::new(inner, move |container| {
+                            config.clone().start_node(index, container.ip().to_string())
+                        });
+                        (bind_mounts, wait)
+                    }
+                    // Failing to write the configuration fails the test
+                    Err(e) => (HashMap::new(), HookWait::fail(e)),
+                };
+
+                let mut composition: dockertest::Composition = ContainerConfig {
+                    args: vec![String::from("server")],
+                    env: env.clone(),
+                    handle: handle.clone(),
+                    name: IMAGE.into(),
+                    source: SOURCE,
+                    version: self.version.clone(),
+                    ports: Some(vec![(PORT, self.port + u32::from(index))]),
+                    wait: Some(Box::new(wait)),
+                    bind_mounts,
+                }
+                .into();
+
+                // Nodes must start in order as they join the first one
+                composition.alias(handle);
+                composition.with_start_policy(dockertest::StartPolicy::Strict)
+            })
+            .collect()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// A single node in a [VaultCluster].
+///
+/// The node URL which is accessible from the local host can be found with
+/// `external_url`, while other running containers should use `internal_url`.
+pub struct VaultClusterNode {
+    pub external_port: u32,
+    pub handle: String,
+    pub internal_port: u32,
+    pub ip: String,
+}
+
+impl VaultClusterNode {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        format!("http://{}", self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost::{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external HTTP address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal HTTP address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+/// A running cluster of Vault servers.
+///
+/// The `nodes` field contains each node in the order they were brought up,
+/// meaning the first node is the one which initialized the cluster. The
+/// `token` field contains the root token of the cluster and `unseal_keys` the
+/// keys generated when it was initialized.
+pub struct VaultCluster {
+    pub nodes: Vec<VaultClusterNode>,
+    pub token: String,
+    pub unseal_keys: Vec<String>,
+}
+
+impl VaultCluster {
+    /// Returns the node which is currently the active node of the cluster.
+    ///
+    /// The first node which responds is asked for the current leader, so this
+    /// continues to work if some of the nodes have been stopped.
+    pub async fn leader(&self) -> Result<Option<&VaultClusterNode>, reqwest::Error> {
+        let client = reqwest::Client::new();
+
+        let mut result = Ok(None);
+        for node in self.nodes.iter() {
+            let resp = client
+                .get(format!("{}/v1/sys/leader", node.external_url()))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            let leader = match resp {
+                Ok(r) => r.json::<LeaderResponse>().await?,
+                Err(e) => {
+                    result = Err(e);
+                    continue;
+                }
+            };
+
+            let host = leader
+                .leader_address
+                .split("://")
+                .last()
+                .and_then(|a| a.split(':').next())
+                .unwrap_or_default()
+                .to_string();
+            return Ok(self.nodes.iter().find(|n| n.handle == host));
+        }
+        result
+    }
+}
+
+impl Server for VaultCluster {
+    type Config = VaultClusterConfig;
+
+    fn new(config: &Self::Config, _: &dockertest::RunningContainer) -> Self {
+        let state = config.state.lock().unwrap();
+        let nodes = (0..config.nodes.max(1))
+            .map(|index| {
+                let handle = config.node_handle(index);
+                VaultClusterNode {
+                    external_port: config.port + u32::from(index),
+                    internal_port: PORT,
+                    ip: state.ips.get(&handle).cloned().unwrap_or_default(),
+                    handle,
+                }
+            })
+            .collect();
+
+        VaultCluster {
+            nodes,
+            token: state.root_token.clone().unwrap_or_default(),
+            unseal_keys: state.unseal_keys.clone(),
         }
     }
 }
@@ -137,8 +1033,13 @@ impl Server for VaultServer {
 #[cfg(test)]
 mod tests {
 
-    use super::{VaultServer, VaultServerConfig};
+    use super::{
+        VaultAuthMethod, VaultCluster, VaultClusterConfig, VaultProvisioning, VaultSecret,
+        VaultSecretEngine, VaultServer, VaultServerConfig, VaultStorage,
+    };
     use crate::Test;
+    use serde_json::json;
+    use std::collections::HashMap;
 
     const PORT: u32 = 9200;
 
@@ -165,4 +1066,185 @@ mod tests {
             assert_eq!(resp.unwrap().status(), 200);
         });
     }
+
+    #[test]
+    fn test_vault_raft() {
+        let config = VaultServerConfig::builder()
+            .port(PORT + 1)
+            .storage(Some(VaultStorage::Raft))
+            .version("1.13.3".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: VaultServer = instance.server();
+            assert_eq!(server.unseal_keys.len(), 5);
+
+            let client = reqwest::Client::new();
+            let resp = client
+                .get(format!("{}/v1/sys/seal-status", server.external_url()))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+            assert_eq!(resp["sealed"], false);
+            assert_eq!(resp["storage_type"], "raft");
+
+            let resp = client
+                .get(format!(
+                    "{}/v1/auth/token/lookup-self",
+                    server.external_url()
+                ))
+                .header("X-Vault-Token", server.token)
+                .send()
+                .await;
+            assert!(resp.is_ok());
+            assert_eq!(resp.unwrap().status(), 200);
+        });
+    }
+
+    #[test]
+    fn test_vault_tls() {
+        let config = VaultServerConfig::builder()
+            .port(PORT + 2)
+            .tls(true)
+            .version("1.13.3".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: VaultServer = instance.server();
+            assert!(server.external_url().starts_with("https://"));
+
+            let ca =
+                reqwest::Certificate::from_pem(server.ca_pem.as_ref().unwrap().as_bytes()).unwrap();
+            let client = reqwest::Client::builder()
+                .add_root_certificate(ca)
+                .build()
+                .unwrap();
+            let resp = client
+                .get(format!(
+                    "{}/v1/auth/token/lookup-self",
+                    server.external_url()
+                ))
+                .header("X-Vault-Token", server.token)
+                .send()
+                .await;
+            assert!(resp.is_ok());
+            assert_eq!(resp.unwrap().status(), 200);
+        });
+    }
+
+    #[test]
+    fn test_vault_provisioning() {
+        let engine = VaultSecretEngine::builder()
+            .kind("kv")
+            .path("kv")
+            .options(HashMap::from([("version".into(), "2".into())]))
+            .build()
+            .unwrap();
+        let approle = VaultAuthMethod::builder()
+            .kind("approle")
+            .path("approle")
+            .roles(HashMap::from([(
+                "test".into(),
+                json!({ "token_policies": ["read-kv"] }),
+            )]))
+            .build()
+            .unwrap();
+        let secret = VaultSecret::builder()
+            .path("kv/data/test")
+            .data(json!({ "data": { "foo": "bar" } }))
+            .build()
+            .unwrap();
+        let provisioning = VaultProvisioning::builder()
+            .auth_methods(vec![approle])
+            .policies(HashMap::from([(
+                "read-kv".into(),
+                r#"path "kv/data/*" { capabilities = ["read"] }"#.into(),
+            )]))
+            .secret_engines(vec![engine])
+            .secrets(vec![secret])
+            .build()
+            .unwrap();
+
+        let config = VaultServerConfig::builder()
+            .port(PORT + 3)
+            .provisioning(provisioning)
+            .version("1.13.3".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: VaultServer = instance.server();
+            let approle = server.approles.get("test").unwrap();
+
+            let client = reqwest::Client::new();
+            let login = client
+                .post(format!("{}/v1/auth/approle/login", server.external_url()))
+                .json(&json!({ "role_id": approle.role_id, "secret_id": approle.secret_id }))
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+            let token = login["auth"]["client_token"].as_str().unwrap();
+
+            let secret = client
+                .get(format!("{}/v1/kv/data/test", server.external_url()))
+                .header("X-Vault-Token", token)
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+            assert_eq!(secret["data"]["data"]["foo"], "bar");
+        });
+    }
+
+    #[test]
+    fn test_vault_cluster() {
+        // Each node is exposed on a consecutive port
+        let config = VaultClusterConfig::builder()
+            .port(PORT + 50)
+            .version("1.13.3".into())
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let cluster: VaultCluster = instance.server();
+            assert_eq!(cluster.nodes.len(), 3);
+
+            let leader = cluster.leader().await.unwrap().unwrap();
+            assert_eq!(leader.handle, cluster.nodes[0].handle);
+
+            let client = reqwest::Client::new();
+            let resp = client
+                .get(format!(
+                    "{}/v1/sys/storage/raft/configuration",
+                    cluster.nodes[2].external_url()
+                ))
+                .header("X-Vault-Token", &cluster.token)
+                .send()
+                .await
+                .unwrap()
+                .json::<serde_json::Value>()
+                .await
+                .unwrap();
+            let servers = resp["data"]["config"]["servers"].as_array().unwrap();
+            assert_eq!(servers.len(), 3);
+        });
+    }
 }
@@ -0,0 +1,39 @@
+/// Contains [Servers][crate::Server] for search engines.
+pub mod elasticsearch;
+pub mod opensearch;
+
+pub use elasticsearch::{ElasticsearchServer, ElasticsearchServerConfig};
+pub use opensearch::{OpenSearchServer, OpenSearchServerConfig};
+
+use crate::waitfor::retry;
+
+/// Polls the `_cluster/health` endpoint at the given base URL until the
+/// cluster reports a yellow or green status.
+async fn wait_for_health(
+    url: String,
+    credentials: Option<(String, String)>,
+    timeout: u16,
+) -> Result<(), String> {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    retry(timeout, || async {
+        let mut request = client.get(format!(
+            "{}/_cluster/health?wait_for_status=yellow&timeout=1s",
+            url
+        ));
+        if let Some((user, pass)) = &credentials {
+            request = request.basic_auth(user, Some(pass));
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    })
+    .await
+}
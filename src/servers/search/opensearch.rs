@@ -0,0 +1,208 @@
+use super::wait_for_health;
+use crate::common::rand_string;
+use crate::waitfor::HookWait;
+use crate::{Config, ContainerConfig, Server};
+use derive_builder::Builder;
+use dockertest::Source;
+use std::collections::HashMap;
+
+const IMAGE: &str = "opensearchproject/opensearch";
+const PORT: u32 = 9200;
+const SOURCE: Source = Source::DockerHub;
+const USER: &str = "admin";
+
+/// Configuration for creating a single node OpenSearch server.
+///
+/// By default the OpenSearch server listens on port 9200 for HTTP requests.
+/// This is exposed on the container by default, but the exposed port can be
+/// controlled by setting the `port` field.
+///
+/// The security plugin is disabled by default. Setting `security` enables it
+/// with the bundled demo certificates, in which case the server is only
+/// available over HTTPS and requests must authenticate as the `admin` user
+/// with the configured `password`. If it's omitted the password will
+/// automatically be generated. The JVM heap size can be controlled with the
+/// `heap` field and defaults to a value suitable for CI environments.
+///
+/// The server is only considered ready once the `_cluster/health` endpoint
+/// reports a yellow or green status.
+///
+/// See the [DockerHub](https://hub.docker.com/r/opensearchproject/opensearch)
+/// repo for more information on the arguments and environment variables that
+/// can be used to configure the server.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct OpenSearchServerConfig {
+    #[builder(default = "Vec::new()")]
+    pub args: Vec<String>,
+    #[builder(default = "HashMap::new()")]
+    pub env: HashMap<String, String>,
+    #[builder(default = "crate::server::new_handle(IMAGE)")]
+    pub handle: String,
+    #[builder(default = "String::from(\"512m\")")]
+    pub heap: String,
+    #[builder(default = "format!(\"{}!Aa1\", rand_string(16))")]
+    pub password: String,
+    #[builder(default = "9200")]
+    pub port: u32,
+    #[builder(default = "false")]
+    pub security: bool,
+    #[builder(default = "60")]
+    pub timeout: u16,
+    #[builder(default = "String::from(\"latest\")")]
+    pub version: String,
+}
+
+impl OpenSearchServerConfig {
+    pub fn builder() -> OpenSearchServerConfigBuilder {
+        OpenSearchServerConfigBuilder::default()
+    }
+}
+
+impl Config for OpenSearchServerConfig {
+    fn into_composition(self) -> dockertest::Composition {
+        let ports = vec![(PORT, self.port)];
+
+        let mut env = self.env.clone();
+        env.insert(String::from("discovery.type"), String::from("single-node"));
+        env.insert(
+            String::from("OPENSEARCH_JAVA_OPTS"),
+            format!("-Xms{} -Xmx{}", self.heap, self.heap),
+        );
+        if self.security {
+            env.insert(
+                String::from("OPENSEARCH_INITIAL_ADMIN_PASSWORD"),
+                self.password.clone(),
+            );
+        } else {
+            env.insert(
+                String::from("DISABLE_SECURITY_PLUGIN"),
+                String::from("true"),
+            );
+            env.insert(
+                String::from("DISABLE_INSTALL_DEMO_CONFIG"),
+                String::from("true"),
+            );
+        }
+
+        let (url, credentials) = match self.security {
+            true => (
+                format!("https://localhost:{}", self.port),
+                Some((USER.to_string(), self.password.clone())),
+            ),
+            false => (format!("http://localhost:{}", self.port), None),
+        };
+        let timeout = self.timeout;
+        let wait = Box::new(HookWait::running(timeout, move |_| {
+            wait_for_health(url.clone(), credentials.clone(), timeout)
+        }));
+
+        ContainerConfig {
+            args: self.args,
+            env,
+            handle: self.handle,
+            name: IMAGE.into(),
+            source: SOURCE,
+            version: self.version,
+            ports: Some(ports),
+            wait: Some(wait),
+            bind_mounts: HashMap::new(),
+        }
+        .into()
+    }
+
+    fn handle(&self) -> &str {
+        self.handle.as_str()
+    }
+}
+
+/// A running instance of an OpenSearch server.
+///
+/// When the security plugin is enabled the server is only available over
+/// HTTPS using a self-signed demo certificate and requests must authenticate
+/// using the `username` and `password` fields.
+pub struct OpenSearchServer {
+    pub external_port: u32,
+    pub internal_port: u32,
+    pub ip: String,
+    pub password: String,
+    pub security: bool,
+    pub username: String,
+}
+
+impl OpenSearchServer {
+    fn format_address(&self, host: &str, port: u32) -> String {
+        format!("{}:{}", host, port)
+    }
+
+    fn format_url(&self, host: &str, port: u32) -> String {
+        let scheme = if self.security { "https" } else { "http" };
+        format!("{}://{}", scheme, self.format_address(host, port))
+    }
+
+    /// The external address in the form of localhost:{port}
+    pub fn external_address(&self) -> String {
+        self.format_address("localhost", self.external_port)
+    }
+
+    /// The external HTTP(S) address
+    pub fn external_url(&self) -> String {
+        self.format_url("localhost", self.external_port)
+    }
+
+    /// The container internal address in the form of {ip}:{port}
+    pub fn internal_address(&self) -> String {
+        self.format_address(self.ip.as_str(), self.internal_port)
+    }
+
+    /// The internal HTTP(S) address
+    pub fn internal_url(&self) -> String {
+        self.format_url(self.ip.as_str(), self.internal_port)
+    }
+}
+
+impl Server for OpenSearchServer {
+    type Config = OpenSearchServerConfig;
+
+    fn new(config: &Self::Config, container: &dockertest::RunningContainer) -> Self {
+        OpenSearchServer {
+            external_port: config.port,
+            internal_port: PORT,
+            ip: container.ip().to_string(),
+            password: config.password.clone(),
+            security: config.security,
+            username: USER.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OpenSearchServer, OpenSearchServerConfig};
+    use crate::Test;
+    use test_log::test;
+
+    const PORT: u32 = 9210;
+
+    #[test]
+    fn test_opensearch() {
+        let config = OpenSearchServerConfig::builder()
+            .port(PORT)
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: OpenSearchServer = instance.server();
+
+            let client = reqwest::Client::new();
+            let resp = client
+                .get(format!("{}/_cluster/health", server.external_url()))
+                .send()
+                .await;
+            assert!(resp.is_ok());
+            assert_eq!(resp.unwrap().status(), 200);
+        });
+    }
+}
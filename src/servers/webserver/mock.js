@@ -0,0 +1,23 @@
+// Responds to requests using the routes generated from the server config
+var fs = require('fs');
+
+var routes = JSON.parse(fs.readFileSync('/etc/nginx/njs/routes.json', 'utf8'));
+
+function respond(r) {
+    var route = r.variables.mock_routes
+        .split(',')
+        .map(function (i) { return routes[Number(i)]; })
+        .find(function (route) { return !route.method || route.method === r.method; });
+
+    if (!route) {
+        r.return(405);
+        return;
+    }
+
+    Object.keys(route.headers).forEach(function (name) {
+        r.headersOut[name] = route.headers[name];
+    });
+    setTimeout(function () { r.return(route.status, route.body); }, route.delay);
+}
+
+export default { respond };
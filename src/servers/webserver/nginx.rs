@@ -1,11 +1,18 @@
+use crate::common::write_tempfile;
+use crate::waitfor::HookWait;
 use crate::{Config, ContainerConfig, Server};
 use derive_builder::Builder;
 use dockertest::{waitfor, Source};
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tempfile::{NamedTempFile, TempPath};
 
 const IMAGE: &str = "nginx";
+const LOCATIONS_DIR: &str = "/etc/nginx/conf.d/locations";
+const NJS_DIR: &str = "/etc/nginx/njs";
 const PORT: u32 = 8888;
 const LOG_MSG: &str = "start worker process";
 const SOURCE: Source = Source::DockerHub;
@@ -33,6 +40,119 @@ impl WebserverContent {
     }
 }
 
+/// A location which proxies requests to another container in the same test.
+///
+/// Requests matching the `location` prefix are passed, with their URI
+/// unchanged, to the container identified by `handle` on the given `port`
+/// using `scheme`. The container is resolved by name when each request is
+/// made, so it doesn't need to be running when the server starts.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct WebserverProxy {
+    #[builder(default = "String::new()", setter(into))]
+    pub handle: String,
+    #[builder(default = "String::from(\"/\")", setter(into))]
+    pub location: String,
+    #[builder(default = "80")]
+    pub port: u32,
+    #[builder(default = "String::from(\"http\")", setter(into))]
+    pub scheme: String,
+}
+
+impl WebserverProxy {
+    pub fn builder() -> WebserverProxyBuilder {
+        WebserverProxyBuilder::default()
+    }
+
+    /// The environment variable containing the name of the upstream container
+    fn env(index: usize) -> String {
+        format!("NGINX_PROXY_{}", index)
+    }
+
+    /// Returns the location block for this proxy as an envsubst template.
+    fn template(&self, index: usize) -> String {
+        format!(
+            r#"
+            location {location} {{
+                set $upstream_{index} {scheme}://${{{env}}}:{port};
+                proxy_pass $upstream_{index};
+                proxy_set_header Host $host;
+                proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;
+                proxy_set_header X-Forwarded-Proto $scheme;
+            }}
+        "#,
+            location = &self.location,
+            index = index,
+            scheme = &self.scheme,
+            env = Self::env(index),
+            port = self.port,
+        )
+    }
+}
+
+/// Determines how the path of a [WebserverRoute] is matched against requests.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum WebserverMatch {
+    #[default]
+    Exact,
+    Prefix,
+    Regex,
+}
+
+/// A route which responds to matching requests with a fixed response.
+///
+/// Requests are matched using the `path` field as determined by `matching`,
+/// where regular expressions use the syntax supported by Nginx. When `method`
+/// is set only requests using that method are matched. The response is sent
+/// after waiting for `delay` with the given `status`, `headers` and `body`.
+///
+/// Routes sharing the same path are matched in the order they were given,
+/// with requests matching none of their methods receiving a 405 response.
+#[derive(Clone, Default, Builder)]
+#[builder(default)]
+pub struct WebserverRoute {
+    #[builder(default = "String::new()", setter(into))]
+    pub body: String,
+    #[builder(default = "Duration::ZERO")]
+    pub delay: Duration,
+    #[builder(default = "HashMap::new()")]
+    pub headers: HashMap<String, String>,
+    #[builder(default = "WebserverMatch::Exact")]
+    pub matching: WebserverMatch,
+    #[builder(default = "None", setter(into, strip_option))]
+    pub method: Option<String>,
+    #[builder(default = "String::from(\"/\")", setter(into))]
+    pub path: String,
+    #[builder(default = "200")]
+    pub status: u16,
+}
+
+impl WebserverRoute {
+    pub fn builder() -> WebserverRouteBuilder {
+        WebserverRouteBuilder::default()
+    }
+
+    /// The location modifier and URI which match this route
+    fn location(&self) -> String {
+        match self.matching {
+            WebserverMatch::Exact => format!("= {}", self.path),
+            WebserverMatch::Prefix => self.path.clone(),
+            WebserverMatch::Regex => format!("~ \"{}\"", self.path),
+        }
+    }
+
+    /// The route as passed to the njs handler
+    fn to_json(&self) -> Value {
+        json!({
+            "body": self.body,
+            "delay": self.delay.as_millis() as u64,
+            "headers": self.headers,
+            "method": self.method.as_ref().map(|m| m.to_uppercase()),
+            "status": self.status,
+        })
+    }
+}
+
 pub type ManagedContent = Vec<TempPath>;
 
 #[derive(Debug)]
@@ -53,6 +173,16 @@ pub struct TlsConfig {
     key_path: String,
 }
 
+/// Configuration for creating an Nginx server.
+///
+/// Content added with `add_web_content`, the proxies listed in `proxies` and
+/// the mock routes listed in `routes` are served as locations of a single
+/// server listening on port 8888. This is exposed on the container by default,
+/// but the exposed port can be controlled by setting the `port` field. The
+/// server terminates TLS when configured using `tls_from_ca_bytes`.
+///
+/// Mock routes are served using the njs module, which is loaded by replacing
+/// the main configuration file of the image when any routes are given.
 #[derive(Clone, Default, Builder)]
 #[builder(default)]
 pub struct NginxServerConfig {
@@ -64,6 +194,10 @@ pub struct NginxServerConfig {
     pub handle: String,
     #[builder(default = "8888")]
     pub port: u32,
+    #[builder(default = "Vec::new()")]
+    pub proxies: Vec<WebserverProxy>,
+    #[builder(default = "Vec::new()")]
+    pub routes: Vec<WebserverRoute>,
     #[builder(default = "10")]
     pub timeout: u16,
     #[builder(default = "String::from(\"latest\")")]
@@ -72,6 +206,8 @@ pub struct NginxServerConfig {
     pub bind_mounts: HashMap<String, String>,
     #[builder(default)]
     pub tls: Option<TlsConfig>,
+    #[builder(setter(skip))]
+    files: Arc<Mutex<Vec<TempPath>>>,
 }
 
 impl NginxServerConfig {
@@ -150,12 +286,62 @@ impl NginxServerConfig {
         let temp_path =
             self.tempfile_mount(&content.name, "content", &content.content, &remote_path)?;
 
+        let config = self.add_location(
+            &content.name,
+            &format!(
+                r#"
+            location ={location} {{
+                default_type {content_type};
+                alias {alias};
+
+                # hack to allow all http methods on static resources
+                error_page  405     =200 $uri;
+            }}
+        "#,
+                location = &content.serve_path,
+                content_type = &content.content_type,
+                alias = &remote_path,
+            ),
+        )?;
+
+        Ok(vec![config, temp_path])
+    }
+
+    /// Adds the given location block to the server under the given name.
+    ///
+    /// The block is prefixed with `content-` so that it can't replace any of
+    /// the generated locations (i.e. `routes.conf` or the rendered proxies).
+    fn add_location(
+        &mut self,
+        name: &str,
+        verbatim_config: &str,
+    ) -> Result<TempPath, ContentError> {
+        self.tempfile_mount(
+            "location",
+            ".conf",
+            verbatim_config.as_bytes(),
+            &format!("{}/content-{}.conf", LOCATIONS_DIR, name),
+        )
+    }
+
+    /// Returns whether any locations were added to the server.
+    fn has_locations(&self) -> bool {
+        !self.proxies.is_empty()
+            || !self.routes.is_empty()
+            || self
+                .bind_mounts
+                .keys()
+                .any(|path| path.starts_with(LOCATIONS_DIR))
+    }
+
+    /// Returns the server block which includes all added locations.
+    fn server_config(&self) -> String {
         let optional_tls_config = match &self.tls {
             Some(c) => format!(
                 r#"ssl default_server;
-                    server_name localhost;
-                    ssl_certificate     {cert};
-                    ssl_certificate_key {key};
+                server_name localhost;
+                ssl_certificate     {cert};
+                ssl_certificate_key {key};
                 "#,
                 cert = &c.cert_path,
                 key = &c.key_path
@@ -163,29 +349,58 @@ impl NginxServerConfig {
             None => "default_server;\n".to_string(),
         };
 
-        let config = self
-            .add_config(&format!(
-                r#"
+        let optional_njs_config = match self.routes.is_empty() {
+            true => String::new(),
+            false => format!("js_import mock from {}/mock.js;", NJS_DIR),
+        };
+
+        format!(
+            r#"
+            {njs_config}
             server {{
                 listen {port} {tls_config}
-                location ={location} {{
-                    default_type {content_type};
-                    alias {alias};
-                }}
+                # resolves the containers used by proxies
+                resolver 127.0.0.11 valid=10s;
 
-                # hack to allow all http methods on static resources
-                error_page  405     =200 $uri;
+                include {locations}/*.conf;
             }}
         "#,
-                port = PORT,
-                location = &content.serve_path,
-                content_type = &content.content_type,
-                alias = &remote_path,
-                tls_config = &optional_tls_config
-            ))?
-            .into_temp_path();
+            njs_config = &optional_njs_config,
+            port = PORT,
+            tls_config = &optional_tls_config,
+            locations = LOCATIONS_DIR,
+        )
+    }
 
-        Ok(vec![config, temp_path])
+    /// Returns the location blocks for the configured routes.
+    ///
+    /// Routes sharing a location are served by the same block, which passes
+    /// the indices of those routes to the njs handler.
+    fn routes_config(&self) -> String {
+        let mut locations: Vec<(String, Vec<String>)> = Vec::new();
+        for (index, route) in self.routes.iter().enumerate() {
+            let location = route.location();
+            match locations.iter_mut().find(|(l, _)| *l == location) {
+                Some((_, indices)) => indices.push(index.to_string()),
+                None => locations.push((location, vec![index.to_string()])),
+            }
+        }
+
+        locations
+            .iter()
+            .map(|(location, indices)| {
+                format!(
+                    r#"
+            location {location} {{
+                set $mock_routes {indices};
+                js_content mock.respond;
+            }}
+        "#,
+                    location = location,
+                    indices = indices.join(","),
+                )
+            })
+            .collect()
     }
 
     // idempotent since bind mounts are key'ed by their target path
@@ -193,6 +408,59 @@ impl NginxServerConfig {
         self.add_config_file("default.conf", "/dev/null");
     }
 
+    /// Writes the configuration for the configured locations and mounts it.
+    fn mount_locations(&mut self) -> Result<(), String> {
+        let mut files = Vec::new();
+        let mut write = |name: &str, content: &str| {
+            let file = write_tempfile(name, ".conf", content.as_bytes(), 0o644)
+                .map_err(|e| format!("failed writing nginx config: {}", e))?;
+            let path = file.to_string_lossy().to_string();
+            files.push(file);
+            Ok::<_, String>(path)
+        };
+
+        let mut mounts = vec![(
+            String::from("/etc/nginx/conf.d/server.conf"),
+            write("server", &self.server_config())?,
+        )];
+        // Templates are rendered into the locations directory on startup
+        for (index, proxy) in self.proxies.iter().enumerate() {
+            mounts.push((
+                format!(
+                    "/etc/nginx/templates/locations/proxy-{}.conf.template",
+                    index
+                ),
+                write("proxy", &proxy.template(index))?,
+            ));
+        }
+
+        if !self.routes.is_empty() {
+            let routes: Vec<Value> = self.routes.iter().map(|r| r.to_json()).collect();
+            mounts.extend([
+                (
+                    String::from("/etc/nginx/nginx.conf"),
+                    write("nginx", include_str!("./nginx.conf"))?,
+                ),
+                (
+                    format!("{}/mock.js", NJS_DIR),
+                    write("mock", include_str!("./mock.js"))?,
+                ),
+                (
+                    format!("{}/routes.json", NJS_DIR),
+                    write("routes", &Value::from(routes).to_string())?,
+                ),
+                (
+                    format!("{}/routes.conf", LOCATIONS_DIR),
+                    write("routes", &self.routes_config())?,
+                ),
+            ]);
+        }
+
+        self.bind_mounts.extend(mounts);
+        self.files.lock().unwrap().extend(files);
+        Ok(())
+    }
+
     fn tempfile_mount(
         &mut self,
         prefix: &str,
@@ -269,17 +537,29 @@ impl NginxServerConfig {
 }
 
 impl Config for NginxServerConfig {
-    fn into_composition(self) -> dockertest::Composition {
+    fn into_composition(mut self) -> dockertest::Composition {
         let ports = vec![(PORT, self.port)];
 
+        let mut mounted = Ok(());
+        if self.has_locations() {
+            self.shadow_upstream_default_site();
+            mounted = self.mount_locations();
+        }
+
         let timeout = self.timeout;
-        let wait = Box::new(waitfor::MessageWait {
+        let inner = Box::new(waitfor::MessageWait {
             message: LOG_MSG.into(),
             source: waitfor::MessageSource::Stderr,
             timeout,
         });
 
-        ContainerConfig {
+        // Failing to write the configuration fails the test
+        let wait: Box<dyn waitfor::WaitFor> = match mounted {
+            Ok(()) => inner,
+            Err(e) => Box::new(HookWait::fail(e)),
+        };
+
+        let mut composition: dockertest::Composition = ContainerConfig {
             args: Vec::new(),
             env: HashMap::new(),
             handle: self.handle,
@@ -290,7 +570,12 @@ impl Config for NginxServerConfig {
             wait: Some(wait),
             bind_mounts: self.bind_mounts,
         }
-        .into()
+        .into();
+
+        for (index, proxy) in self.proxies.iter().enumerate() {
+            composition.inject_container_name(proxy.handle.clone(), WebserverProxy::env(index));
+        }
+        composition
     }
 
     fn handle(&self) -> &str {
@@ -353,9 +638,14 @@ impl Server for NginxServer {
 
 #[cfg(test)]
 mod tests {
-    use super::{NginxServer, NginxServerConfig, WebserverContent};
+    use super::{
+        NginxServer, NginxServerConfig, WebserverContent, WebserverMatch, WebserverProxy,
+        WebserverRoute, LOCATIONS_DIR,
+    };
     use crate::Test;
     use reqwest::Certificate;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
     use test_log::test;
 
     #[test]
@@ -441,4 +731,172 @@ mod tests {
             assert_eq!(&resp, &payload);
         });
     }
+
+    #[test]
+    fn test_proxy() {
+        let payload = r#"{"hello": "world!"}"#.to_string();
+
+        let mut upstream = NginxServerConfig::builder().port(8890).build().unwrap();
+        let _hello_world = upstream
+            .add_web_content(
+                WebserverContent::builder()
+                    .name("hello")
+                    .content(payload.as_bytes().to_vec())
+                    .content_type("application/json")
+                    .serve_path("/api/hello")
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+
+        let mut config = NginxServerConfig::builder()
+            .port(8889)
+            .proxies(vec![WebserverProxy::builder()
+                .handle(upstream.handle.clone())
+                .location("/api")
+                .port(8888)
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+        let _certs = config
+            .tls_from_ca_bytes(include_bytes!("./ca.crt"), include_bytes!("./ca.key"))
+            .unwrap();
+
+        // Both servers share a config type, so each is looked up by its config
+        let proxy = config.clone();
+        let mut test = Test::new();
+        test.register(upstream);
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: NginxServer = instance.server_for(&proxy);
+
+            let client = reqwest::Client::builder()
+                .add_root_certificate(Certificate::from_pem(include_bytes!("./ca.crt")).unwrap())
+                .build()
+                .unwrap();
+
+            let resp = client
+                .get(format!("{}/api/hello", server.external_url()))
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap();
+
+            assert_eq!(&resp, &payload);
+        });
+    }
+
+    #[test]
+    fn test_location_names() {
+        let mut config = NginxServerConfig::builder()
+            .proxies(vec![WebserverProxy::builder()
+                .handle("upstream")
+                .build()
+                .unwrap()])
+            .routes(vec![WebserverRoute::builder()
+                .path("/mock")
+                .build()
+                .unwrap()])
+            .build()
+            .unwrap();
+        let mut files = Vec::new();
+        for name in ["routes", "proxy-0"] {
+            let content = WebserverContent::builder()
+                .name(name)
+                .content(name.as_bytes().to_vec())
+                .serve_path(format!("/{}", name))
+                .build()
+                .unwrap();
+            files.push(config.add_web_content(content).unwrap());
+        }
+        config.mount_locations().unwrap();
+
+        // User content never shares a path with the generated locations
+        let routes = format!("{}/routes.conf", LOCATIONS_DIR);
+        let content = format!("{}/content-routes.conf", LOCATIONS_DIR);
+        assert_ne!(config.bind_mounts[&routes], config.bind_mounts[&content]);
+        assert!(config
+            .bind_mounts
+            .contains_key(&format!("{}/content-proxy-0.conf", LOCATIONS_DIR)));
+        assert!(!config
+            .bind_mounts
+            .contains_key(&format!("{}/proxy-0.conf", LOCATIONS_DIR)));
+    }
+
+    #[test]
+    fn test_routes() {
+        let config = NginxServerConfig::builder()
+            .port(8891)
+            .routes(vec![
+                WebserverRoute::builder()
+                    .headers(HashMap::from([(
+                        String::from("Retry-After"),
+                        String::from("10"),
+                    )]))
+                    .method("GET")
+                    .path("/status")
+                    .status(503)
+                    .build()
+                    .unwrap(),
+                WebserverRoute::builder()
+                    .body(r#"{"id": 1}"#)
+                    .matching(WebserverMatch::Prefix)
+                    .method("POST")
+                    .path("/items")
+                    .status(201)
+                    .build()
+                    .unwrap(),
+                WebserverRoute::builder()
+                    .delay(Duration::from_millis(500))
+                    .matching(WebserverMatch::Regex)
+                    .path("^/users/[0-9]+$")
+                    .build()
+                    .unwrap(),
+            ])
+            .build()
+            .unwrap();
+        let mut test = Test::new();
+        test.register(config);
+
+        test.run(|instance| async move {
+            let server: NginxServer = instance.server();
+            let client = reqwest::Client::new();
+
+            let resp = client
+                .get(format!("{}/status", server.external_url()))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 503);
+            assert_eq!(resp.headers()["Retry-After"], "10");
+
+            let resp = client
+                .post(format!("{}/status", server.external_url()))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 405);
+
+            let resp = client
+                .post(format!("{}/items/new", server.external_url()))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 201);
+            assert_eq!(resp.text().await.unwrap(), r#"{"id": 1}"#);
+
+            let start = Instant::now();
+            let resp = client
+                .delete(format!("{}/users/1", server.external_url()))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(resp.status(), 200);
+            assert!(start.elapsed() >= Duration::from_millis(500));
+        });
+    }
 }
@@ -37,7 +37,7 @@ impl Test {
     /// test body is ran.
     pub fn register(&mut self, config: impl Config + 'static) {
         self.configs.insert(config.clone());
-        self.compositions.push(config.into_composition());
+        self.compositions.extend(config.into_compositions());
     }
 
     /// Brings up the [Servers][Server] registered with this test and then
@@ -107,6 +107,17 @@ impl TestInstance {
     /// was created.
     pub fn server<S: Server>(&self) -> S {
         let config = self.configs.get::<S::Config>().unwrap();
+        self.server_for(config)
+    }
+
+    /// Returns an instance of a [Server] created from the given [Config].
+    ///
+    /// Only the last registered [Config] of each type can be found by
+    /// `server`, so this method is used to access each of the [Servers][Server]
+    /// when more than one [Config] of the same type was registered with a
+    /// [Test]. The given [Config] should be a copy of the one which was
+    /// registered.
+    pub fn server_for<S: Server>(&self, config: &S::Config) -> S {
         let container = self.op.handle(config.handle());
         S::new(config, container)
     }
@@ -0,0 +1,127 @@
+/// Contains [WaitFor] implementations and helpers used by servers which need
+/// more than a log message to determine readiness.
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dockertest::waitfor::{async_trait, NoWait, RunningWait, WaitFor};
+use dockertest::{DockerTestError, PendingContainer, RunningContainer};
+use futures::future::BoxFuture;
+
+type Hook = Arc<dyn Fn(RunningContainer) -> BoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// A [WaitFor] which runs an asynchronous hook after an inner [WaitFor] has
+/// completed.
+///
+/// This is useful for servers which must be polled before they can be
+/// considered available or which need resources provisioned through their API
+/// before a test body runs. The container is only considered ready once the
+/// hook returns successfully. Any error returned by the hook fails the test.
+#[derive(Clone)]
+pub struct HookWait {
+    pub inner: Box<dyn WaitFor>,
+    pub hook: Hook,
+}
+
+impl HookWait {
+    /// Creates a new [HookWait] which runs `hook` once `inner` has completed.
+    pub fn new<F, Fut>(inner: Box<dyn WaitFor>, hook: F) -> Self
+    where
+        F: Fn(RunningContainer) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        HookWait {
+            inner,
+            hook: Arc::new(move |container| Box::pin(hook(container))),
+        }
+    }
+
+    /// Creates a new [HookWait] which runs `hook` as soon as the container is
+    /// reported as running.
+    pub fn running<F, Fut>(timeout: u16, hook: F) -> Self
+    where
+        F: Fn(RunningContainer) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let inner = Box::new(RunningWait {
+            check_interval: 1,
+            max_checks: timeout.into(),
+        });
+        Self::new(inner, hook)
+    }
+
+    /// Creates a new [HookWait] which fails with the given error as soon as
+    /// the container is started.
+    ///
+    /// This is used by configurations which fail to prepare the resources a
+    /// container needs (i.e. generated files) so that the test fails with the
+    /// error instead of panicking while building the composition.
+    pub fn fail(error: String) -> Self {
+        Self::new(Box::new(NoWait {}), move |_| {
+            let error = error.clone();
+            async move { Err(error) }
+        })
+    }
+}
+
+#[async_trait]
+impl WaitFor for HookWait {
+    async fn wait_for_ready(
+        &self,
+        container: PendingContainer,
+    ) -> Result<RunningContainer, DockerTestError> {
+        let container = self.inner.wait_for_ready(container).await?;
+        (self.hook)(container.clone())
+            .await
+            .map_err(DockerTestError::Startup)?;
+        Ok(container)
+    }
+}
+
+/// Repeatedly calls `fun` until it succeeds or `timeout` seconds have passed.
+///
+/// The last error returned by `fun` is returned if it never succeeds.
+pub async fn retry<T, F, Fut>(timeout: u16, mut fun: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, String>>,
+{
+    let deadline = Instant::now() + Duration::from_secs(timeout.into());
+    loop {
+        match fun().await {
+            Ok(v) => return Ok(v),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => tokio::time::sleep(Duration::from_millis(500)).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::retry;
+
+    #[test]
+    fn test_retry() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+
+        let mut attempts = 0;
+        let result = rt.block_on(retry(5, || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                match attempt {
+                    3 => Ok(attempt),
+                    _ => Err(String::from("not yet")),
+                }
+            }
+        }));
+        assert_eq!(result, Ok(3));
+
+        let result: Result<(), String> =
+            rt.block_on(retry(0, || async { Err(String::from("failed")) }));
+        assert_eq!(result, Err(String::from("failed")));
+    }
+}